@@ -0,0 +1,330 @@
+//! Merkle trees for DEFAI swap distributions.
+//!
+//! Leaves are `keccak(user || amount_le)` and each pair is sorted before
+//! hashing, so a proof is just the list of sibling hashes. `verify_proof` is
+//! the exact fold `defai_swap` runs in `swap_og_tier0_for_pnft_v6` and
+//! `claim_airdrop`; the program calls it directly.
+
+use solana_program::keccak::hashv;
+use solana_program::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub type Hash = [u8; 32];
+
+/// Leaf hash for a (wallet, amount) entry
+pub fn leaf_hash(user: &Pubkey, amount: u64) -> Hash {
+    hashv(&[user.as_ref(), &amount.to_le_bytes()]).0
+}
+
+/// Parent hash of two nodes; the smaller node goes first
+pub fn hash_pair(a: &Hash, b: &Hash) -> Hash {
+    if a <= b {
+        hashv(&[a, b]).0
+    } else {
+        hashv(&[b, a]).0
+    }
+}
+
+/// Folds the proof from the leaf up and compares against the root
+pub fn verify_proof(user: &Pubkey, amount: u64, proof: &[Hash], root: &Hash) -> bool {
+    let computed = proof
+        .iter()
+        .fold(leaf_hash(user, amount), |acc, sibling| hash_pair(&acc, sibling));
+    computed == *root
+}
+
+/// One row of a distribution CSV
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub wallet: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MerkleError {
+    Empty,
+    MissingColumn { line: usize },
+    InvalidKey { line: usize, value: String },
+    InvalidAmount { line: usize, value: String },
+    DuplicateWallet { line: usize, first_line: usize, wallet: Pubkey },
+    AmountOverflow,
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::Empty => write!(f, "no entries found"),
+            MerkleError::MissingColumn { line } => {
+                write!(f, "line {}: expected `wallet,amount`", line)
+            }
+            MerkleError::InvalidKey { line, value } => {
+                write!(f, "line {}: invalid wallet `{}`", line, value)
+            }
+            MerkleError::InvalidAmount { line, value } => {
+                write!(f, "line {}: invalid amount `{}`", line, value)
+            }
+            MerkleError::DuplicateWallet { line, first_line, wallet } => write!(
+                f,
+                "line {}: wallet {} already listed on line {}",
+                line, wallet, first_line
+            ),
+            MerkleError::AmountOverflow => write!(f, "total amount overflows u64"),
+        }
+    }
+}
+
+impl std::error::Error for MerkleError {}
+
+/// Parses `wallet,amount` rows. Blank lines are skipped, a first row whose
+/// amount is not a number is treated as a header, and extra columns are ignored.
+/// Invalid or default wallets, zero amounts and duplicate wallets are rejected.
+pub fn parse_csv(input: &str) -> Result<Vec<Entry>, MerkleError> {
+    let mut entries = Vec::new();
+    let mut seen: HashMap<Pubkey, usize> = HashMap::new();
+    let mut first_row = true;
+
+    for (index, raw) in input.lines().enumerate() {
+        let line = index + 1;
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let is_first_row = std::mem::replace(&mut first_row, false);
+        let mut fields = raw.split(',').map(|field| field.trim().trim_matches('"'));
+        let (wallet, amount) = match (fields.next(), fields.next()) {
+            (Some(wallet), Some(amount)) => (wallet, amount),
+            _ => return Err(MerkleError::MissingColumn { line }),
+        };
+
+        let amount = match amount.parse::<u64>() {
+            Ok(amount) if amount > 0 => amount,
+            // Header row
+            Err(_) if is_first_row && Pubkey::from_str(wallet).is_err() => continue,
+            _ => {
+                return Err(MerkleError::InvalidAmount {
+                    line,
+                    value: amount.to_string(),
+                })
+            }
+        };
+        let wallet = match Pubkey::from_str(wallet) {
+            Ok(key) if key != Pubkey::default() => key,
+            _ => {
+                return Err(MerkleError::InvalidKey {
+                    line,
+                    value: wallet.to_string(),
+                })
+            }
+        };
+        if let Some(first_line) = seen.insert(wallet, line) {
+            return Err(MerkleError::DuplicateWallet {
+                line,
+                first_line,
+                wallet,
+            });
+        }
+        entries.push(Entry { wallet, amount });
+    }
+
+    if entries.is_empty() {
+        return Err(MerkleError::Empty);
+    }
+    Ok(entries)
+}
+
+/// Sum of all entry amounts, for the distribution's `total_amount`
+pub fn total_amount(entries: &[Entry]) -> Result<u64, MerkleError> {
+    entries.iter().try_fold(0u64, |total, entry| {
+        total.checked_add(entry.amount).ok_or(MerkleError::AmountOverflow)
+    })
+}
+
+/// Merkle tree over entry leaves, kept in input order. A node without a
+/// sibling is carried up unchanged, so its proof simply skips that level.
+pub struct MerkleTree {
+    layers: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(entries: &[Entry]) -> Result<Self, MerkleError> {
+        if entries.is_empty() {
+            return Err(MerkleError::Empty);
+        }
+        let mut layers = vec![entries
+            .iter()
+            .map(|entry| leaf_hash(&entry.wallet, entry.amount))
+            .collect::<Vec<_>>()];
+        while layers[layers.len() - 1].len() > 1 {
+            let next = layers[layers.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+        Ok(MerkleTree { layers })
+    }
+
+    pub fn root(&self) -> Hash {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    /// Sibling hashes from the leaf at `index` up to the root
+    pub fn proof(&self, mut index: usize) -> Vec<Hash> {
+        let mut proof = Vec::new();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        proof
+    }
+}
+
+/// Lowercase hex encoding of a hash
+pub fn to_hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET_A: &str = "4vJ9JU1bJJE96FWSJKvHsmmFADCg4gpZQff4P3bkLKi";
+    const WALLET_B: &str = "8qbHbw2BbbTHBW1sbeqakYXVKRQM8Ne7pLK7m6CVfeR";
+    const WALLET_C: &str = "CktRuQ2mttgRGkXJtyksdKHjUdc2C4TgDzyB98oEzy8";
+
+    fn entries(count: usize) -> Vec<Entry> {
+        (0..count)
+            .map(|i| Entry {
+                wallet: Pubkey::new_unique(),
+                amount: 1_000 * (i as u64 + 1),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_proof_verifies() {
+        for count in [1, 2, 3, 4, 5, 7, 8, 9, 17] {
+            let entries = entries(count);
+            let tree = MerkleTree::new(&entries).unwrap();
+            let root = tree.root();
+            for (index, entry) in entries.iter().enumerate() {
+                let proof = tree.proof(index);
+                assert!(
+                    verify_proof(&entry.wallet, entry.amount, &proof, &root),
+                    "leaf {} of {}",
+                    index,
+                    count
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn single_leaf_is_the_root() {
+        let entries = entries(1);
+        let tree = MerkleTree::new(&entries).unwrap();
+        assert_eq!(tree.root(), leaf_hash(&entries[0].wallet, entries[0].amount));
+        assert!(tree.proof(0).is_empty());
+        assert!(verify_proof(&entries[0].wallet, entries[0].amount, &[], &tree.root()));
+    }
+
+    #[test]
+    fn tampered_claims_fail() {
+        let entries = entries(5);
+        let tree = MerkleTree::new(&entries).unwrap();
+        let root = tree.root();
+        let entry = &entries[2];
+        let proof = tree.proof(2);
+
+        assert!(!verify_proof(&entry.wallet, entry.amount + 1, &proof, &root));
+        assert!(!verify_proof(&entries[3].wallet, entry.amount, &proof, &root));
+
+        let mut bad_proof = proof.clone();
+        bad_proof[0][0] ^= 1;
+        assert!(!verify_proof(&entry.wallet, entry.amount, &bad_proof, &root));
+        assert!(!verify_proof(&entry.wallet, entry.amount, &proof[1..], &root));
+        assert!(!verify_proof(&entry.wallet, entry.amount, &proof, &[0; 32]));
+    }
+
+    #[test]
+    fn parse_csv_skips_header_and_blank_lines() {
+        let csv = format!("wallet,amount\n\n{},100\n\"{}\", 200 ,extra\n", WALLET_A, WALLET_B);
+        let entries = parse_csv(&csv).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry { wallet: Pubkey::from_str(WALLET_A).unwrap(), amount: 100 },
+                Entry { wallet: Pubkey::from_str(WALLET_B).unwrap(), amount: 200 },
+            ]
+        );
+        assert_eq!(total_amount(&entries), Ok(300));
+    }
+
+    #[test]
+    fn parse_csv_only_skips_a_leading_header() {
+        let csv = format!("{},100\nwallet,amount\n", WALLET_A);
+        assert_eq!(
+            parse_csv(&csv),
+            Err(MerkleError::InvalidAmount { line: 2, value: "amount".to_string() })
+        );
+    }
+
+    #[test]
+    fn parse_csv_rejects_duplicates() {
+        let csv = format!("{},100\n{},200\n{},300\n", WALLET_A, WALLET_B, WALLET_A);
+        assert_eq!(
+            parse_csv(&csv),
+            Err(MerkleError::DuplicateWallet {
+                line: 3,
+                first_line: 1,
+                wallet: Pubkey::from_str(WALLET_A).unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_csv_rejects_default_key() {
+        let default_key = Pubkey::default().to_string();
+        let csv = format!("{},100\n{},200\n", WALLET_A, default_key);
+        assert_eq!(
+            parse_csv(&csv),
+            Err(MerkleError::InvalidKey { line: 2, value: default_key })
+        );
+    }
+
+    #[test]
+    fn parse_csv_rejects_zero_amount() {
+        let csv = format!("{},0\n", WALLET_A);
+        assert_eq!(
+            parse_csv(&csv),
+            Err(MerkleError::InvalidAmount { line: 1, value: "0".to_string() })
+        );
+    }
+
+    #[test]
+    fn parse_csv_rejects_empty_input() {
+        assert_eq!(parse_csv("wallet,amount\n\n"), Err(MerkleError::Empty));
+        assert_eq!(parse_csv("wallet\n"), Err(MerkleError::MissingColumn { line: 1 }));
+    }
+
+    #[test]
+    fn known_csv_root() {
+        let csv = format!(
+            "wallet,amount\n{},1000000\n{},2500000\n{},42\n",
+            WALLET_A, WALLET_B, WALLET_C
+        );
+        let entries = parse_csv(&csv).unwrap();
+        let tree = MerkleTree::new(&entries).unwrap();
+        assert_eq!(
+            to_hex(&tree.root()),
+            "957fdd721366f4ee72bd923143d585309cdfa1169d06d970b80519ca1b4f5328"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Builds a distribution Merkle tree from a `wallet,amount` CSV.
+//!
+//! Usage: defai_merkle <input.csv> [--out <proofs.json>]
+//!
+//! Prints (or writes) the root and one proof per wallet as JSON, after
+//! checking every proof with the same `verify_proof` the program runs.
+
+use defai_merkle::{parse_csv, to_hex, total_amount, verify_proof, MerkleTree};
+use serde_json::{json, Map, Value};
+use std::process::ExitCode;
+
+fn run() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let mut input = None;
+    let mut out = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" | "-o" => out = Some(args.next().ok_or("--out needs a path")?),
+            "--help" | "-h" => {
+                println!("Usage: defai_merkle <input.csv> [--out <proofs.json>]");
+                return Ok(());
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let input = input.ok_or("missing input CSV; see --help")?;
+
+    let csv = std::fs::read_to_string(&input).map_err(|e| format!("{}: {}", input, e))?;
+    let entries = parse_csv(&csv).map_err(|e| format!("{}: {}", input, e))?;
+    let total = total_amount(&entries).map_err(|e| e.to_string())?;
+    let tree = MerkleTree::new(&entries).map_err(|e| e.to_string())?;
+    let root = tree.root();
+
+    let mut proofs = Map::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let proof = tree.proof(index);
+        // Self-check with the on-chain fold
+        if !verify_proof(&entry.wallet, entry.amount, &proof, &root) {
+            return Err(format!("self-check failed for {}", entry.wallet));
+        }
+        proofs.insert(
+            entry.wallet.to_string(),
+            json!({
+                "index": index,
+                "amount": entry.amount,
+                "proof": proof.iter().map(to_hex).collect::<Vec<_>>(),
+                "proof_bytes": proof,
+            }),
+        );
+    }
+
+    let output = json!({
+        "root": to_hex(&root),
+        "root_bytes": root,
+        "total_amount": total,
+        "count": entries.len(),
+        "proofs": Value::Object(proofs),
+    });
+    let rendered = serde_json::to_string_pretty(&output).map_err(|e| e.to_string())?;
+    match out {
+        Some(path) => {
+            std::fs::write(&path, rendered).map_err(|e| format!("{}: {}", path, e))?;
+            eprintln!("Wrote {} proofs to {}", entries.len(), path);
+        }
+        None => println!("{}", rendered),
+    }
+    eprintln!("Root: {} ({} wallets, total {})", to_hex(&root), entries.len(), total);
+    eprintln!("Self-check passed");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,26 +1,89 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, transfer_checked, TransferChecked};
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::program::set_return_data;
+use anchor_spl::token_interface::{
+    Mint, TokenAccount, TokenInterface, transfer_checked, TransferChecked, mint_to, MintTo, burn, Burn,
+};
+
+#[cfg(feature = "cpi")]
+pub mod tier_cpi;
 
 declare_id!("2TLhCW35y5jcuoKtfwTx7H5EPMqUtCf3UQhYKdKKg3Hq");
 
-// Constants for sustainable economics
-pub const GOLD_MIN: u64 = 10_000_000 * 10u64.pow(6);      // 10M DEFAI
-pub const GOLD_MAX: u64 = 99_999_999 * 10u64.pow(6);      // 99.99M DEFAI
-pub const GOLD_APY_BPS: u16 = 50;                         // 0.5% = 50 basis points
+// Default cooldown before unbonding tickets can be withdrawn
+pub const DEFAULT_UNBONDING_PERIOD: i64 = 7 * 24 * 60 * 60; // 7 days
+pub const MIN_UNBONDING_PERIOD: i64 = 24 * 60 * 60;          // 1 day
+pub const MAX_UNBONDING_PERIOD: i64 = 30 * 24 * 60 * 60;     // 30 days
 
-pub const TITANIUM_MIN: u64 = 100_000_000 * 10u64.pow(6); // 100M DEFAI
-pub const TITANIUM_MAX: u64 = 999_999_999 * 10u64.pow(6); // 999.99M DEFAI
-pub const TITANIUM_APY_BPS: u16 = 75;                     // 0.75% = 75 basis points
+// Lock-up options for StakePosition: (lock days, reward multiplier bps)
+pub const LOCK_OPTIONS: [(u16, u16); 4] = [
+    (7, 10_000),   // 1.00x
+    (30, 11_000),  // 1.10x
+    (90, 12_500),  // 1.25x
+    (365, 15_000), // 1.50x
+];
 
-pub const INFINITE_MIN: u64 = 1_000_000_000 * 10u64.pow(6); // 1B DEFAI
-pub const INFINITE_APY_BPS: u16 = 100;                      // 1% = 100 basis points
+// Tier table limits (tiers themselves live in the TierConfig account)
+pub const MAX_TIERS: usize = 10;
+pub const MAX_TIER_NAME_LEN: usize = 32;
 
 pub const SECONDS_PER_YEAR: u64 = 31_536_000;
 pub const BASIS_POINTS: u64 = 10_000;
 
+// Penalty curve limits (the curve itself lives in the PenaltyConfig account)
+pub const MAX_PENALTY_STEPS: usize = 8;
+pub const MAX_PENALTY_BPS: u16 = 2_000; // 20%
+
+// Cap on the keeper tip taken from auto-compounded rewards
+pub const MAX_COMPOUND_TIP_BPS: u16 = 500; // 5%
+
+// Maximum number of additional reward streams (partner tokens) on top of DEFAI rewards
+pub const MAX_REWARD_STREAMS: usize = 4;
+
+// Fixed-point scale for acc_reward_per_share
+pub const ACC_REWARD_PRECISION: u128 = 1_000_000_000_000;
+
 // Timelock duration for admin actions
 pub const ADMIN_TIMELOCK_DURATION: i64 = 48 * 60 * 60; // 48 hours
 
+// Loyalty boost limits (steps and cap live in ProgramState)
+pub const MAX_LOYALTY_STEPS: usize = 8;
+pub const MAX_LOYALTY_BONUS_BPS: u16 = 10_000; // +100%
+
+// Cap on the referral reward share of a referee's rewards
+pub const MAX_REFERRAL_BPS: u16 = 2_000; // 20%
+
+// Upper bound on StakingCouncil members
+pub const MAX_COUNCIL_MEMBERS: usize = 10;
+
+// Zero-balance stakes untouched this long can be closed by the authority's sweeper
+pub const DORMANT_STAKE_PERIOD: i64 = 180 * 24 * 60 * 60; // 180 days
+
+// Voting power checkpoint ring sizes (per user and global)
+pub const MAX_VOTER_CHECKPOINTS: usize = 64;
+pub const MAX_TOTAL_CHECKPOINTS: usize = 256;
+
+// Anyone can switch on emergency mode once the program has been paused this long
+pub const EMERGENCY_PAUSE_THRESHOLD: i64 = 14 * 24 * 60 * 60; // 14 days
+
+// Current account layout versions; bump when a field is added and extend the migrate_* instructions
+pub const PROGRAM_STATE_VERSION: u8 = 1;
+pub const USER_STAKE_VERSION: u8 = 1;
+pub const REWARD_ESCROW_VERSION: u8 = 1;
+
+// Data sizes (without discriminator) of the original, unversioned layouts
+pub const LEGACY_PROGRAM_STATE_LEN: usize = 32 + 32 + 8 + 8 + 1 + 1 + 1 + 1 + 33 + 8;
+pub const LEGACY_USER_STAKE_LEN: usize = 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8;
+pub const LEGACY_REWARD_ESCROW_LEN: usize = 32 + 8 + 8 + 1;
+
+// Linear APY tiers of the original layout: (min staked amount, APY bps), highest first.
+// Only used to settle what unmigrated stakes accrued before switching to the reward index.
+pub const LEGACY_TIER_APYS: [(u64, u16); 3] = [
+    (1_000_000_000 * 10u64.pow(6), 100), // Infinite: 1B DEFAI, 1%
+    (100_000_000 * 10u64.pow(6), 75),    // Titanium: 100M DEFAI, 0.75%
+    (10_000_000 * 10u64.pow(6), 50),     // Gold: 10M DEFAI, 0.5%
+];
+
 // TODO: Replace with actual program initializer authority pubkey for production
 // Example: pub const PROGRAM_INITIALIZER: Pubkey = pubkey!("ActualInitializerPubkeyHere");
 // For now, we'll add logging to track who initializes the program
@@ -55,6 +118,26 @@ pub mod defai_staking {
         program_state.escrow_vault_bump = 0;  // Will be set in initialize_escrow
         program_state.pending_authority = None;
         program_state.authority_change_timestamp = 0;
+        program_state.acc_reward_per_share = 0;
+        program_state.last_reward_timestamp = Clock::get()?.unix_timestamp;
+        program_state.reward_rate = 0; // Set via update_reward_rate once the escrow is funded
+        program_state.total_reward_shares = 0;
+        program_state.total_pending_rewards = 0;
+        program_state.unbonding_period = DEFAULT_UNBONDING_PERIOD;
+        program_state.total_unbonding = 0;
+        program_state.liquid_reward_shares = 0;
+        program_state.reward_streams = [RewardStream::default(); MAX_REWARD_STREAMS];
+        program_state.epoch_duration = 0; // Emission schedule off until configure_emission_schedule
+        program_state.current_epoch = 0;
+        program_state.epoch_start_timestamp = 0;
+        program_state.epoch_reward_budget = 0;
+        program_state.next_epoch_reward_budget = 0;
+        program_state.paused_at = 0;
+        program_state.emergency_mode = false;
+        program_state.emergency_mode_timestamp = 0;
+        program_state.compound_interval = 24 * 60 * 60; // Daily
+        program_state.compound_tip_bps = 0;
+        program_state.version = PROGRAM_STATE_VERSION;
         
         Ok(())
     }
@@ -81,6 +164,7 @@ pub mod defai_staking {
         escrow.total_balance = 0;
         escrow.total_distributed = 0;
         escrow.bump = ctx.bumps.reward_escrow;
+        escrow.version = REWARD_ESCROW_VERSION;
         
         Ok(())
     }
@@ -89,7 +173,14 @@ pub mod defai_staking {
         ctx: Context<FundEscrow>,
         amount: u64,
     ) -> Result<()> {
+        let funding_limit = ctx.accounts.program_state.escrow_funding_limit;
+        require!(
+            funding_limit == 0 || amount <= funding_limit,
+            StakingError::EscrowFundingLimitExceeded
+        );
+        
         // Transfer tokens from funder to escrow
+        let vault_balance_before = ctx.accounts.escrow_token_account.amount;
         let transfer_ctx = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             TransferChecked {
@@ -100,6 +191,7 @@ pub mod defai_staking {
             },
         );
         transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        let amount = received_amount(&mut ctx.accounts.escrow_token_account, vault_balance_before)?;
         
         // Update escrow balance
         let escrow = &mut ctx.accounts.reward_escrow;
@@ -114,19 +206,310 @@ pub mod defai_staking {
         Ok(())
     }
 
+    // Extra reserve kept above outstanding reward liabilities when withdrawing from escrow
+    pub fn set_escrow_safety_buffer(ctx: Context<ManageEscrow>, safety_buffer_bps: u16) -> Result<()> {
+        ctx.accounts.reward_escrow.safety_buffer_bps = safety_buffer_bps;
+        
+        emit!(EscrowSafetyBufferUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            safety_buffer_bps,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn propose_escrow_withdrawal(ctx: Context<ManageEscrow>, amount: u64) -> Result<()> {
+        require!(amount > 0, StakingError::InvalidEscrowWithdrawal);
+        
+        let escrow = &mut ctx.accounts.reward_escrow;
+        escrow.pending_withdrawal = amount;
+        escrow.withdrawal_unlock_timestamp = Clock::get()?.unix_timestamp + ADMIN_TIMELOCK_DURATION;
+        
+        emit!(EscrowWithdrawalProposedEvent {
+            authority: ctx.accounts.authority.key(),
+            amount,
+            executable_at: escrow.withdrawal_unlock_timestamp,
+        });
+        
+        msg!("Escrow withdrawal proposed. Can be executed after {}", escrow.withdrawal_unlock_timestamp);
+        
+        Ok(())
+    }
+    
+    pub fn cancel_escrow_withdrawal(ctx: Context<ManageEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.reward_escrow;
+        require!(escrow.pending_withdrawal > 0, StakingError::NoPendingEscrowWithdrawal);
+        
+        emit!(EscrowWithdrawalCancelledEvent {
+            authority: ctx.accounts.authority.key(),
+            amount: escrow.pending_withdrawal,
+        });
+        
+        escrow.pending_withdrawal = 0;
+        escrow.withdrawal_unlock_timestamp = 0;
+        
+        Ok(())
+    }
+    
+    // Only the surplus above outstanding reward liabilities plus the safety buffer can leave
+    pub fn withdraw_escrow(ctx: Context<WithdrawEscrow>) -> Result<()> {
+        let clock = Clock::get()?;
+        let amount = ctx.accounts.reward_escrow.pending_withdrawal;
+        require!(amount > 0, StakingError::NoPendingEscrowWithdrawal);
+        require!(
+            clock.unix_timestamp >= ctx.accounts.reward_escrow.withdrawal_unlock_timestamp,
+            StakingError::TimelockNotExpired
+        );
+        
+        // Bring liabilities up to date before measuring the surplus. Referral rewards are owed on
+        // top of staking rewards: settled ones are in total_referral_liability, and referral_bps of
+        // total_pending_rewards is held back for referees whose rewards aren't settled yet.
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        let unsettled_referrals = (program_state.total_pending_rewards as u128)
+            .checked_mul(program_state.referral_bps as u128)
+            .unwrap()
+            .checked_div(BASIS_POINTS as u128)
+            .unwrap() as u64;
+        let liabilities = program_state.total_pending_rewards
+            .checked_add(program_state.total_referral_liability)
+            .unwrap()
+            .checked_add(unsettled_referrals)
+            .unwrap();
+        let buffer = (liabilities as u128)
+            .checked_mul(ctx.accounts.reward_escrow.safety_buffer_bps as u128)
+            .unwrap()
+            .checked_div(BASIS_POINTS as u128)
+            .unwrap() as u64;
+        let balance_before = ctx.accounts.reward_escrow.total_balance;
+        let surplus = balance_before
+            .saturating_sub(liabilities)
+            .saturating_sub(buffer);
+        require!(amount <= surplus, StakingError::EscrowWithdrawalExceedsSurplus);
+        
+        let program_state_key = program_state.key();
+        let escrow_seeds = &[
+            b"reward-escrow",
+            program_state_key.as_ref(),
+            &[program_state.reward_escrow_bump],
+        ];
+        let escrow_signer = &[&escrow_seeds[..]];
+        
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.reward_escrow.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            escrow_signer,
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        
+        let escrow = &mut ctx.accounts.reward_escrow;
+        escrow.total_balance = escrow.total_balance.checked_sub(amount).unwrap();
+        escrow.pending_withdrawal = 0;
+        escrow.withdrawal_unlock_timestamp = 0;
+        
+        emit!(EscrowWithdrawnEvent {
+            authority: ctx.accounts.authority.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            liabilities,
+            safety_buffer: buffer,
+            balance_before,
+            balance_after: escrow.total_balance,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    pub fn add_reward_stream(
+        ctx: Context<AddRewardStream>,
+        reward_rate: u64,
+        end_timestamp: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(end_timestamp > clock.unix_timestamp, StakingError::InvalidRewardStream);
+        
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        
+        let reward_mint = ctx.accounts.reward_mint.key();
+        require!(
+            !program_state.reward_streams.iter().any(|stream| stream.reward_mint == reward_mint),
+            StakingError::InvalidRewardStream
+        );
+        let stream_index = program_state.reward_streams
+            .iter()
+            .position(|stream| stream.reward_mint == Pubkey::default())
+            .ok_or(StakingError::RewardStreamsFull)?;
+        
+        // Streams start from zero: existing stakers' debts for this slot are already zero
+        program_state.reward_streams[stream_index] = RewardStream {
+            reward_mint,
+            reward_rate,
+            end_timestamp,
+            acc_reward_per_share: 0,
+            last_reward_timestamp: clock.unix_timestamp,
+            total_balance: 0,
+            total_distributed: 0,
+            total_pending_rewards: 0,
+            vault_bump: ctx.bumps.stream_vault,
+        };
+        
+        emit!(RewardStreamAddedEvent {
+            authority: ctx.accounts.authority.key(),
+            stream_index: stream_index as u8,
+            reward_mint,
+            reward_rate,
+            end_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn update_reward_stream(
+        ctx: Context<UpdateRewardRate>,
+        stream_index: u8,
+        reward_rate: u64,
+        end_timestamp: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        apply_reward_stream_update(
+            &mut ctx.accounts.program_state,
+            stream_index,
+            reward_rate,
+            end_timestamp,
+            clock.unix_timestamp,
+        )?;
+        
+        emit!(RewardStreamUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            stream_index,
+            reward_rate,
+            end_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn fund_reward_stream(
+        ctx: Context<FundRewardStream>,
+        stream_index: u8,
+        amount: u64,
+    ) -> Result<()> {
+        let vault_balance_before = ctx.accounts.stream_vault.amount;
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.funder_token_account.to_account_info(),
+                to: ctx.accounts.stream_vault.to_account_info(),
+                authority: ctx.accounts.funder.to_account_info(),
+                mint: ctx.accounts.reward_mint.to_account_info(),
+            },
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.reward_mint.decimals)?;
+        let amount = received_amount(&mut ctx.accounts.stream_vault, vault_balance_before)?;
+        
+        let stream = get_reward_stream_mut(&mut ctx.accounts.program_state, stream_index)?;
+        stream.total_balance = stream.total_balance.checked_add(amount).unwrap();
+        
+        emit!(RewardStreamFundedEvent {
+            funder: ctx.accounts.funder.key(),
+            stream_index,
+            reward_mint: ctx.accounts.reward_mint.key(),
+            amount,
+            new_balance: stream.total_balance,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn claim_stream_rewards(
+        ctx: Context<ClaimStreamRewards>,
+        stream_index: u8,
+    ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+        
+        update_reward_index(&mut ctx.accounts.program_state, clock.unix_timestamp)?;
+        settle_rewards(&mut ctx.accounts.program_state, user_stake)?;
+        
+        // Validate the index before touching the per-stream balances
+        let stream_balance = get_reward_stream_mut(&mut ctx.accounts.program_state, stream_index)?.total_balance;
+        let slot = stream_index as usize;
+        let claimable = *user_stake.stream_rewards_owed
+            .get(slot)
+            .ok_or(StakingError::InvalidRewardStream)?;
+        require!(claimable > 0, StakingError::NoRewards);
+        require!(stream_balance >= claimable, StakingError::InsufficientEscrowBalance);
+        
+        // Stream vaults are owned by the reward escrow PDA, same as the DEFAI escrow vault
+        let program_state_key = ctx.accounts.program_state.key();
+        let escrow_seeds = &[
+            b"reward-escrow",
+            program_state_key.as_ref(),
+            &[ctx.accounts.program_state.reward_escrow_bump],
+        ];
+        let escrow_signer = &[&escrow_seeds[..]];
+        
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.stream_vault.to_account_info(),
+                to: ctx.accounts.user_reward_account.to_account_info(),
+                authority: ctx.accounts.reward_escrow.to_account_info(),
+                mint: ctx.accounts.reward_mint.to_account_info(),
+            },
+            escrow_signer,
+        );
+        transfer_checked(transfer_ctx, claimable, ctx.accounts.reward_mint.decimals)?;
+        
+        user_stake.stream_rewards_owed[slot] = 0;
+        
+        let program_state = &mut ctx.accounts.program_state;
+        let stream = get_reward_stream_mut(program_state, stream_index)?;
+        stream.total_balance = stream.total_balance.checked_sub(claimable).unwrap();
+        stream.total_distributed = stream.total_distributed.checked_add(claimable).unwrap();
+        stream.total_pending_rewards = stream.total_pending_rewards.saturating_sub(claimable);
+        let total_distributed = stream.total_distributed;
+        
+        // Pick up any tier table changes since the user's last interaction
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        
+        emit!(StreamRewardsClaimedEvent {
+            user: ctx.accounts.user.key(),
+            stream_index,
+            reward_mint: ctx.accounts.reward_mint.key(),
+            amount: claimable,
+            total_distributed,
+        });
+        
+        Ok(())
+    }
+
+    // `referrer` is only accepted on the first stake and needs the referrer's ReferralAccount
+    // plus this wallet's ReferralLink; passing them on later stakes adds to the referred volume
     pub fn stake_tokens(
         ctx: Context<StakeTokens>,
         amount: u64,
+        referrer: Option<Pubkey>,
     ) -> Result<()> {
         let program_state = &ctx.accounts.program_state;
         
         // Check if program is paused
         require!(!program_state.paused, StakingError::ProgramPaused);
         
-        // Check minimum amount for Gold tier
-        require!(amount >= GOLD_MIN, StakingError::AmountTooLow);
+        // Check minimum amount for the lowest staking tier (tier 0 is the unstaked band)
+        let tier_config = &ctx.accounts.tier_config;
+        require!(amount >= tier_config.tiers[1].min_amount, StakingError::AmountTooLow);
         
         // Transfer tokens from user to stake vault
+        let vault_balance_before = ctx.accounts.stake_vault.amount;
         let transfer_ctx = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             TransferChecked {
@@ -137,53 +520,188 @@ pub mod defai_staking {
             },
         );
         transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        // Transfer-fee mints deliver less than `amount`; only what arrived is staked
+        let amount = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
         
         // Create or update user stake account
+        let program_state = &mut ctx.accounts.program_state;
         let user_stake = &mut ctx.accounts.user_stake;
         let clock = Clock::get()?;
         
-        if user_stake.owner == Pubkey::default() {
+        // Bring the global reward index up to date before any share changes
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        
+        let is_new_stake = user_stake.owner == Pubkey::default();
+        if is_new_stake {
             // New stake
             user_stake.owner = ctx.accounts.user.key();
+            user_stake.version = USER_STAKE_VERSION;
             user_stake.staked_amount = amount;
             user_stake.stake_timestamp = clock.unix_timestamp;
             user_stake.last_stake_timestamp = clock.unix_timestamp;  // Set both timestamps for new stake
             user_stake.last_claim_timestamp = clock.unix_timestamp;
-            user_stake.locked_until = clock.unix_timestamp + 7 * 24 * 60 * 60; // 7 day initial lock
             user_stake.rewards_earned = 0;
             user_stake.rewards_claimed = 0;
+            user_stake.reward_shares = 0;
+            user_stake.reward_debt = 0;
             
             // Update global stats
-            let program_state = &mut ctx.accounts.program_state;
             program_state.total_users += 1;
         } else {
-            // Calculate pending rewards before adding new stake
-            let pending_rewards = calculate_rewards(
-                user_stake.staked_amount,
-                get_tier_apy(user_stake.staked_amount)?,
-                user_stake.last_claim_timestamp,
-                clock.unix_timestamp,
-            )?;
+            // Settle pending rewards before adding new stake
+            settle_rewards(program_state, user_stake)?;
             
-            user_stake.rewards_earned = user_stake.rewards_earned.checked_add(pending_rewards).unwrap();
             user_stake.staked_amount = user_stake.staked_amount.checked_add(amount).unwrap();
             user_stake.last_claim_timestamp = clock.unix_timestamp;
             user_stake.last_stake_timestamp = clock.unix_timestamp;  // Update last stake timestamp on additional stakes
-            user_stake.locked_until = clock.unix_timestamp + 7 * 24 * 60 * 60; // Extend lock period for additional stakes
         }
         
-        // Update tier based on new total
-        user_stake.tier = get_tier(user_stake.staked_amount)?;
+        // Record the referral on the first stake; later stakes only add volume
+        match (ctx.accounts.referral_link.as_mut(), ctx.accounts.referral_account.as_mut()) {
+            (Some(link), Some(referral_account)) => {
+                if link.referee == Pubkey::default() {
+                    let referrer = referrer.ok_or(StakingError::InvalidReferral)?;
+                    require!(is_new_stake, StakingError::InvalidReferral);
+                    require_keys_neq!(referrer, ctx.accounts.user.key(), StakingError::InvalidReferral);
+                    require_keys_eq!(referrer, referral_account.referrer, StakingError::InvalidReferral);
+                    
+                    link.referee = ctx.accounts.user.key();
+                    link.referrer = referrer;
+                    link.bump = ctx.bumps.referral_link;
+                    referral_account.referred_count = referral_account.referred_count.checked_add(1).unwrap();
+                    
+                    emit!(ReferralRecordedEvent {
+                        referrer,
+                        referee: link.referee,
+                        amount,
+                    });
+                } else {
+                    require!(referrer.is_none(), StakingError::InvalidReferral);
+                    require_keys_eq!(link.referrer, referral_account.referrer, StakingError::InvalidReferral);
+                }
+                // Also re-marks a stake re-created after close_user_stake
+                user_stake.referred = true;
+                referral_account.referred_volume = referral_account.referred_volume.checked_add(amount).unwrap();
+            }
+            (None, None) => require!(referrer.is_none(), StakingError::InvalidReferral),
+            _ => return err!(StakingError::InvalidReferral),
+        }
+        
+        // Update tier and reward shares based on new total
+        update_reward_shares(program_state, tier_config, user_stake, clock.unix_timestamp)?;
+        require!(user_stake.tier > 0, StakingError::AmountTooLow);
+        
+        // Lock (or extend the lock) for the resulting tier's lock period
+        let lock_days = tier_config.tiers[user_stake.tier as usize].lock_days;
+        user_stake.locked_until = clock.unix_timestamp + lock_days as i64 * 24 * 60 * 60;
         
         // Update total staked
-        let program_state = &mut ctx.accounts.program_state;
         program_state.total_staked = program_state.total_staked.checked_add(amount).unwrap();
         
+        // Record the new voting power for governance snapshots
+        let voter_history = &mut ctx.accounts.voter_history;
+        if voter_history.owner == Pubkey::default() {
+            voter_history.owner = ctx.accounts.user.key();
+            voter_history.bump = ctx.bumps.voter_history;
+        }
+        checkpoint_voting_power(
+            voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
         emit!(StakeEvent {
             user: ctx.accounts.user.key(),
             amount,
             tier: user_stake.tier,
             total_staked: user_stake.staked_amount,
+            loyalty_bonus_bps: user_stake.loyalty_bonus_bps,
+        });
+        
+        Ok(())
+    }
+
+    // Stake from the payer's tokens into `beneficiary`'s UserStake. An existing stake keeps its
+    // lock and penalty clock so third parties can't extend them.
+    pub fn stake_for(
+        ctx: Context<StakeFor>,
+        beneficiary: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        
+        let tier_config = &ctx.accounts.tier_config;
+        require!(amount >= tier_config.tiers[1].min_amount, StakingError::AmountTooLow);
+        
+        let vault_balance_before = ctx.accounts.stake_vault.amount;
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.payer_token_account.to_account_info(),
+                to: ctx.accounts.stake_vault.to_account_info(),
+                authority: ctx.accounts.payer.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        // Transfer-fee mints deliver less than `amount`; only what arrived is staked
+        let amount = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
+        
+        let program_state = &mut ctx.accounts.program_state;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+        
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        
+        let is_new_stake = user_stake.owner == Pubkey::default();
+        if is_new_stake {
+            user_stake.owner = beneficiary;
+            user_stake.version = USER_STAKE_VERSION;
+            user_stake.staked_amount = amount;
+            user_stake.stake_timestamp = clock.unix_timestamp;
+            user_stake.last_stake_timestamp = clock.unix_timestamp;
+            user_stake.last_claim_timestamp = clock.unix_timestamp;
+            user_stake.rewards_earned = 0;
+            user_stake.rewards_claimed = 0;
+            user_stake.reward_shares = 0;
+            user_stake.reward_debt = 0;
+            
+            program_state.total_users += 1;
+        } else {
+            settle_rewards(program_state, user_stake)?;
+            user_stake.staked_amount = user_stake.staked_amount.checked_add(amount).unwrap();
+        }
+        
+        update_reward_shares(program_state, tier_config, user_stake, clock.unix_timestamp)?;
+        require!(user_stake.tier > 0, StakingError::AmountTooLow);
+        
+        if is_new_stake {
+            let lock_days = tier_config.tiers[user_stake.tier as usize].lock_days;
+            user_stake.locked_until = clock.unix_timestamp + lock_days as i64 * 24 * 60 * 60;
+        }
+        
+        program_state.total_staked = program_state.total_staked.checked_add(amount).unwrap();
+        
+        let voter_history = &mut ctx.accounts.voter_history;
+        if voter_history.owner == Pubkey::default() {
+            voter_history.owner = beneficiary;
+            voter_history.bump = ctx.bumps.voter_history;
+        }
+        checkpoint_voting_power(
+            voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        emit!(StakedForEvent {
+            payer: ctx.accounts.payer.key(),
+            beneficiary,
+            amount,
+            tier: user_stake.tier,
+            total_staked: user_stake.staked_amount,
+            locked_until: user_stake.locked_until,
         });
         
         Ok(())
@@ -204,24 +722,21 @@ pub mod defai_staking {
             StakingError::TokensLocked
         );
         
-        // Check sufficient balance
+        // Check sufficient balance (principal held in positions must be unstaked per position)
         require!(
-            user_stake.staked_amount >= amount,
+            user_stake.staked_amount.checked_sub(user_stake.position_amount).unwrap() >= amount,
             StakingError::InsufficientStake
         );
         
-        // Calculate pending rewards before unstaking
-        let pending_rewards = calculate_rewards(
-            user_stake.staked_amount,
-            get_tier_apy(user_stake.staked_amount)?,
-            user_stake.last_claim_timestamp,
-            clock.unix_timestamp,
-        )?;
-        user_stake.rewards_earned = user_stake.rewards_earned.checked_add(pending_rewards).unwrap();
+        // Settle pending rewards before unstaking
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
         user_stake.last_claim_timestamp = clock.unix_timestamp;
         
         // Calculate unstaking penalty using last stake timestamp
         let penalty = calculate_unstake_penalty(
+            &ctx.accounts.penalty_config,
             user_stake.last_stake_timestamp,
             clock.unix_timestamp,
             amount,
@@ -250,39 +765,56 @@ pub mod defai_staking {
         );
         transfer_checked(transfer_ctx, amount_after_penalty, ctx.accounts.defai_mint.decimals)?;
         
-        // If there's a penalty, transfer it to escrow
+        // Route the penalty to the configured destination
+        let destination = ctx.accounts.penalty_config.destination;
+        let penalty_to_escrow = route_penalty(
+            destination,
+            penalty,
+            &ctx.accounts.token_program,
+            &ctx.accounts.stake_vault,
+            &mut ctx.accounts.escrow_token_account,
+            ctx.accounts.treasury_token_account.as_deref(),
+            &ctx.accounts.defai_mint,
+            signer,
+        )?;
+        let escrow = &mut ctx.accounts.reward_escrow;
+        escrow.total_balance = escrow.total_balance.checked_add(penalty_to_escrow).unwrap();
         if penalty > 0 {
-            let transfer_penalty_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.stake_vault.to_account_info(),
-                    to: ctx.accounts.escrow_token_account.to_account_info(),
-                    authority: ctx.accounts.stake_vault.to_account_info(),
-                    mint: ctx.accounts.defai_mint.to_account_info(),
-                },
-                signer,
-            );
-            transfer_checked(transfer_penalty_ctx, penalty, ctx.accounts.defai_mint.decimals)?;
-            
-            // Update escrow balance
-            let escrow = &mut ctx.accounts.reward_escrow;
-            escrow.total_balance = escrow.total_balance.checked_add(penalty).unwrap();
+            emit!(PenaltyRoutedEvent {
+                user: ctx.accounts.user.key(),
+                penalty,
+                destination,
+                received: penalty_to_escrow,
+            });
         }
         
         // Update user stake
+        reset_loyalty(user_stake, amount, clock.unix_timestamp)?;
         user_stake.staked_amount = user_stake.staked_amount.checked_sub(amount).unwrap();
         
-        // Update tier
-        if user_stake.staked_amount > 0 {
-            user_stake.tier = get_tier(user_stake.staked_amount)?;
-        } else {
-            user_stake.tier = 0;
+        // Pull the unstaker's shares out before a redistributed penalty is credited, so it only
+        // reaches the remaining stakers (their own rewards were settled above)
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_reward_shares = program_state.total_reward_shares
+            .checked_sub(user_stake.reward_shares)
+            .unwrap();
+        user_stake.reward_shares = 0;
+        if destination == PenaltyDestination::Redistribute {
+            distribute_penalty(program_state, penalty_to_escrow)?;
         }
         
-        // Update global stats
-        let program_state = &mut ctx.accounts.program_state;
+        // Update tier and global stats
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        
         program_state.total_staked = program_state.total_staked.checked_sub(amount).unwrap();
         
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
         emit!(UnstakeEvent {
             user: ctx.accounts.user.key(),
             amount,
@@ -294,366 +826,4745 @@ pub mod defai_staking {
         Ok(())
     }
 
-    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
-        // Enforce pause
+    pub fn open_position(
+        ctx: Context<OpenPosition>,
+        amount: u64,
+        lock_days: u16,
+    ) -> Result<()> {
         require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
-        let user_stake = &mut ctx.accounts.user_stake;
-        let clock = Clock::get()?;
+        require!(amount > 0, StakingError::AmountTooLow);
+        let multiplier_bps = get_lock_multiplier(lock_days).ok_or(StakingError::InvalidLockDuration)?;
         
-        // Calculate pending rewards
-        let pending_rewards = calculate_rewards(
-            user_stake.staked_amount,
-            get_tier_apy(user_stake.staked_amount)?,
-            user_stake.last_claim_timestamp,
-            clock.unix_timestamp,
-        )?;
-        
-        let total_claimable = user_stake.rewards_earned
-            .checked_add(pending_rewards).unwrap()
-            .checked_sub(user_stake.rewards_claimed).unwrap();
-        
-        require!(total_claimable > 0, StakingError::NoRewards);
+        // Transfer tokens from user to stake vault
+        let vault_balance_before = ctx.accounts.stake_vault.amount;
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.stake_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        // Transfer-fee mints deliver less than `amount`; only what arrived is staked
+        let amount = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
+        
+        let program_state = &mut ctx.accounts.program_state;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+        
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        
+        if user_stake.owner == Pubkey::default() {
+            // First interaction: the aggregate UserStake starts empty and unlocked
+            user_stake.owner = ctx.accounts.user.key();
+            user_stake.version = USER_STAKE_VERSION;
+            user_stake.stake_timestamp = clock.unix_timestamp;
+            user_stake.last_stake_timestamp = clock.unix_timestamp;
+            user_stake.last_claim_timestamp = clock.unix_timestamp;
+            user_stake.locked_until = clock.unix_timestamp;
+            program_state.total_users += 1;
+        } else {
+            settle_rewards(program_state, user_stake)?;
+        }
+        
+        // Positions keep their own lock and penalty clock; the aggregate lock is untouched
+        let position = &mut ctx.accounts.stake_position;
+        position.owner = ctx.accounts.user.key();
+        position.index = user_stake.position_count;
+        position.amount = amount;
+        position.lock_days = lock_days;
+        position.multiplier_bps = multiplier_bps;
+        position.stake_timestamp = clock.unix_timestamp;
+        position.locked_until = clock.unix_timestamp + lock_days as i64 * 24 * 60 * 60;
+        position.bump = ctx.bumps.stake_position;
+        
+        user_stake.position_count = user_stake.position_count.checked_add(1).unwrap();
+        user_stake.staked_amount = user_stake.staked_amount.checked_add(amount).unwrap();
+        user_stake.position_amount = user_stake.position_amount.checked_add(amount).unwrap();
+        user_stake.position_bonus_amount = user_stake.position_bonus_amount
+            .checked_add(get_lock_bonus(amount, multiplier_bps)?)
+            .unwrap();
+        
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        require!(user_stake.tier > 0, StakingError::AmountTooLow);
+        
+        program_state.total_staked = program_state.total_staked.checked_add(amount).unwrap();
+        
+        // Record the new voting power for governance snapshots
+        let voter_history = &mut ctx.accounts.voter_history;
+        if voter_history.owner == Pubkey::default() {
+            voter_history.owner = ctx.accounts.user.key();
+            voter_history.bump = ctx.bumps.voter_history;
+        }
+        checkpoint_voting_power(
+            voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        emit!(PositionOpenedEvent {
+            user: ctx.accounts.user.key(),
+            position_index: position.index,
+            amount,
+            lock_days,
+            multiplier_bps,
+            locked_until: position.locked_until,
+            tier: user_stake.tier,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn unstake_position(
+        ctx: Context<UnstakePosition>,
+        position_index: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let user_stake = &mut ctx.accounts.user_stake;
+        let position = &ctx.accounts.stake_position;
+        let clock = Clock::get()?;
         
-        // Check escrow has sufficient balance
         require!(
-            ctx.accounts.reward_escrow.total_balance >= total_claimable,
-            StakingError::InsufficientEscrowBalance
+            clock.unix_timestamp >= position.locked_until,
+            StakingError::TokensLocked
         );
         
-        // Transfer rewards from escrow to user
+        let amount = position.amount;
+        let multiplier_bps = position.multiplier_bps;
+        
+        // Settle pending rewards before removing the position's shares
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
+        
+        // Penalty runs from when this position was opened, not the wallet's latest stake
+        let penalty = calculate_unstake_penalty(
+            &ctx.accounts.penalty_config,
+            position.stake_timestamp,
+            clock.unix_timestamp,
+            amount,
+        )?;
+        
+        let amount_after_penalty = amount.checked_sub(penalty).unwrap();
+        
         let program_state_key = ctx.accounts.program_state.key();
-        let escrow_seeds = &[
-            b"reward-escrow",
+        let seeds = &[
+            b"stake-vault",
             program_state_key.as_ref(),
-            &[ctx.accounts.program_state.reward_escrow_bump],
+            &[ctx.accounts.program_state.vault_bump],
         ];
-        let escrow_signer = &[&escrow_seeds[..]];
+        let signer = &[&seeds[..]];
         
         let transfer_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             TransferChecked {
-                from: ctx.accounts.escrow_token_account.to_account_info(),
+                from: ctx.accounts.stake_vault.to_account_info(),
                 to: ctx.accounts.user_token_account.to_account_info(),
-                authority: ctx.accounts.reward_escrow.to_account_info(),
+                authority: ctx.accounts.stake_vault.to_account_info(),
                 mint: ctx.accounts.defai_mint.to_account_info(),
             },
-            escrow_signer,
+            signer,
         );
-        transfer_checked(transfer_ctx, total_claimable, ctx.accounts.defai_mint.decimals)?;
-        
-        // Update user stake
-        user_stake.rewards_earned = user_stake.rewards_earned.checked_add(pending_rewards).unwrap();
-        user_stake.rewards_claimed = user_stake.rewards_claimed.checked_add(total_claimable).unwrap();
-        user_stake.last_claim_timestamp = clock.unix_timestamp;
+        transfer_checked(transfer_ctx, amount_after_penalty, ctx.accounts.defai_mint.decimals)?;
         
-        // Update escrow
+        // Route the penalty to the configured destination
+        let destination = ctx.accounts.penalty_config.destination;
+        let penalty_to_escrow = route_penalty(
+            destination,
+            penalty,
+            &ctx.accounts.token_program,
+            &ctx.accounts.stake_vault,
+            &mut ctx.accounts.escrow_token_account,
+            ctx.accounts.treasury_token_account.as_deref(),
+            &ctx.accounts.defai_mint,
+            signer,
+        )?;
         let escrow = &mut ctx.accounts.reward_escrow;
-        escrow.total_balance = escrow.total_balance.checked_sub(total_claimable).unwrap();
-        escrow.total_distributed = escrow.total_distributed.checked_add(total_claimable).unwrap();
+        escrow.total_balance = escrow.total_balance.checked_add(penalty_to_escrow).unwrap();
+        if penalty > 0 {
+            emit!(PenaltyRoutedEvent {
+                user: ctx.accounts.user.key(),
+                penalty,
+                destination,
+                received: penalty_to_escrow,
+            });
+        }
         
-        emit!(RewardsClaimedEvent {
+        reset_loyalty(user_stake, amount, clock.unix_timestamp)?;
+        user_stake.staked_amount = user_stake.staked_amount.checked_sub(amount).unwrap();
+        user_stake.position_amount = user_stake.position_amount.checked_sub(amount).unwrap();
+        user_stake.position_bonus_amount = user_stake.position_bonus_amount
+            .checked_sub(get_lock_bonus(amount, multiplier_bps)?)
+            .unwrap();
+        
+        // Pull the unstaker's shares out before a redistributed penalty is credited, so it only
+        // reaches the remaining stakers (their own rewards were settled above)
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_reward_shares = program_state.total_reward_shares
+            .checked_sub(user_stake.reward_shares)
+            .unwrap();
+        user_stake.reward_shares = 0;
+        if destination == PenaltyDestination::Redistribute {
+            distribute_penalty(program_state, penalty_to_escrow)?;
+        }
+        
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        
+        program_state.total_staked = program_state.total_staked.checked_sub(amount).unwrap();
+        
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        // Position account is closed back to the user by the `close` constraint
+        emit!(PositionClosedEvent {
             user: ctx.accounts.user.key(),
-            amount: total_claimable,
-            total_distributed: escrow.total_distributed,
+            position_index,
+            amount,
+            penalty,
+            remaining_stake: user_stake.staked_amount,
+            new_tier: user_stake.tier,
         });
         
         Ok(())
     }
 
-    pub fn propose_authority_change(
-        ctx: Context<UpdateAuthority>,
-        new_authority: Pubkey,
+    // Move a lock-up position to another wallet. Both sides settle rewards first; the
+    // position keeps its amount, multiplier, penalty clock and unlock time.
+    pub fn transfer_stake_position(
+        ctx: Context<TransferStakePosition>,
+        position_index: u64,
+        new_owner: Pubkey,
     ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        require_keys_neq!(new_owner, ctx.accounts.user.key(), StakingError::InvalidOwner);
+        
+        let clock = Clock::get()?;
         let program_state = &mut ctx.accounts.program_state;
-        program_state.pending_authority = Some(new_authority);
-        program_state.authority_change_timestamp = Clock::get()?.unix_timestamp + ADMIN_TIMELOCK_DURATION;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let new_owner_stake = &mut ctx.accounts.new_owner_stake;
+        let position = &ctx.accounts.stake_position;
         
-        emit!(AuthorityUpdatedEvent {
-            old_authority: program_state.authority,
-            new_authority,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
         
-        msg!("Authority change proposed. Can be executed after {}", program_state.authority_change_timestamp);
+        if new_owner_stake.owner == Pubkey::default() {
+            // First interaction: the aggregate UserStake starts empty and unlocked
+            new_owner_stake.owner = new_owner;
+            new_owner_stake.version = USER_STAKE_VERSION;
+            new_owner_stake.stake_timestamp = clock.unix_timestamp;
+            new_owner_stake.last_stake_timestamp = clock.unix_timestamp;
+            new_owner_stake.last_claim_timestamp = clock.unix_timestamp;
+            new_owner_stake.locked_until = clock.unix_timestamp;
+            program_state.total_users += 1;
+        } else {
+            settle_rewards(program_state, new_owner_stake)?;
+        }
+        
+        let amount = position.amount;
+        let bonus = get_lock_bonus(amount, position.multiplier_bps)?;
+        
+        let new_position = &mut ctx.accounts.new_position;
+        new_position.owner = new_owner;
+        new_position.index = new_owner_stake.position_count;
+        new_position.amount = amount;
+        new_position.lock_days = position.lock_days;
+        new_position.multiplier_bps = position.multiplier_bps;
+        new_position.stake_timestamp = position.stake_timestamp;
+        new_position.locked_until = position.locked_until;
+        new_position.bump = ctx.bumps.new_position;
+        
+        reset_loyalty(user_stake, amount, clock.unix_timestamp)?;
+        user_stake.staked_amount = user_stake.staked_amount.checked_sub(amount).unwrap();
+        user_stake.position_amount = user_stake.position_amount.checked_sub(amount).unwrap();
+        user_stake.position_bonus_amount = user_stake.position_bonus_amount.checked_sub(bonus).unwrap();
+        
+        new_owner_stake.position_count = new_owner_stake.position_count.checked_add(1).unwrap();
+        new_owner_stake.staked_amount = new_owner_stake.staked_amount.checked_add(amount).unwrap();
+        new_owner_stake.position_amount = new_owner_stake.position_amount.checked_add(amount).unwrap();
+        new_owner_stake.position_bonus_amount = new_owner_stake.position_bonus_amount.checked_add(bonus).unwrap();
+        
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        update_reward_shares(program_state, &ctx.accounts.tier_config, new_owner_stake, clock.unix_timestamp)?;
+        require!(new_owner_stake.tier > 0, StakingError::AmountTooLow);
+        
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        let new_owner_history = &mut ctx.accounts.new_owner_voter_history;
+        if new_owner_history.owner == Pubkey::default() {
+            new_owner_history.owner = new_owner;
+            new_owner_history.bump = ctx.bumps.new_owner_voter_history;
+        }
+        checkpoint_voting_power(
+            new_owner_history,
+            &mut ctx.accounts.total_voting_power,
+            new_owner_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        // Old position account is closed back to the user by the `close` constraint
+        emit!(PositionTransferredEvent {
+            from: ctx.accounts.user.key(),
+            to: new_owner,
+            old_position_index: position_index,
+            new_position_index: new_position.index,
+            amount,
+            locked_until: new_position.locked_until,
+        });
         
         Ok(())
     }
-    
-    pub fn accept_authority_change(
-        ctx: Context<UpdateAuthority>,
+
+    // Move flexible (non-position) stake to another wallet. Both sides settle rewards first and
+    // keep what they had accrued; the receiver inherits the later lock and penalty clock.
+    pub fn transfer_user_stake(
+        ctx: Context<TransferUserStake>,
+        amount: u64,
+        new_owner: Pubkey,
     ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        require!(amount > 0, StakingError::AmountTooLow);
+        require_keys_neq!(new_owner, ctx.accounts.user.key(), StakingError::InvalidOwner);
+        
+        let clock = Clock::get()?;
         let program_state = &mut ctx.accounts.program_state;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let new_owner_stake = &mut ctx.accounts.new_owner_stake;
         
         require!(
-            program_state.pending_authority.is_some(),
-            StakingError::NoPendingAuthorityChange
-        );
-        require!(
-            Clock::get()?.unix_timestamp >= program_state.authority_change_timestamp,
-            StakingError::TimelockNotExpired
+            user_stake.staked_amount.checked_sub(user_stake.position_amount).unwrap() >= amount,
+            StakingError::InsufficientStake
         );
         
-        let old_authority = program_state.authority;
-        let new_authority = program_state.pending_authority.unwrap();
-        program_state.authority = new_authority;
-        program_state.pending_authority = None;
-        program_state.authority_change_timestamp = 0;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
         
-        emit!(AuthorityUpdatedEvent {
-            old_authority,
-            new_authority,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+        if new_owner_stake.owner == Pubkey::default() {
+            // First interaction: the aggregate UserStake starts empty and unlocked
+            new_owner_stake.owner = new_owner;
+            new_owner_stake.version = USER_STAKE_VERSION;
+            new_owner_stake.stake_timestamp = clock.unix_timestamp;
+            new_owner_stake.last_stake_timestamp = clock.unix_timestamp;
+            new_owner_stake.last_claim_timestamp = clock.unix_timestamp;
+            program_state.total_users += 1;
+            // A new receiver takes over the moved stake's lock and penalty clock
+            new_owner_stake.locked_until = user_stake.locked_until;
+            new_owner_stake.last_stake_timestamp = user_stake.last_stake_timestamp;
+        } else {
+            // An existing stake keeps its lock and penalty clock so the sender can't extend
+            // them. The moved stake can't be stricter than either, or the transfer would shed it.
+            require!(
+                user_stake.locked_until <= new_owner_stake.locked_until.max(clock.unix_timestamp)
+                    && user_stake.last_stake_timestamp <= new_owner_stake.last_stake_timestamp,
+                StakingError::TokensLocked
+            );
+            settle_rewards(program_state, new_owner_stake)?;
+        }
         
-        msg!("Authority changed from {} to {}", old_authority, new_authority);
+        reset_loyalty(user_stake, amount, clock.unix_timestamp)?;
+        user_stake.staked_amount = user_stake.staked_amount.checked_sub(amount).unwrap();
+        new_owner_stake.staked_amount = new_owner_stake.staked_amount.checked_add(amount).unwrap();
         
-        Ok(())
-    }
-
-    pub fn pause_program(ctx: Context<PauseProgram>, paused: bool) -> Result<()> {
-        let program_state = &mut ctx.accounts.program_state;
-        program_state.paused = paused;
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        update_reward_shares(program_state, &ctx.accounts.tier_config, new_owner_stake, clock.unix_timestamp)?;
+        require!(new_owner_stake.tier > 0, StakingError::AmountTooLow);
         
-        emit!(ProgramPausedEvent {
-            authority: ctx.accounts.authority.key(),
-            paused,
-            timestamp: Clock::get()?.unix_timestamp,
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        let new_owner_history = &mut ctx.accounts.new_owner_voter_history;
+        if new_owner_history.owner == Pubkey::default() {
+            new_owner_history.owner = new_owner;
+            new_owner_history.bump = ctx.bumps.new_owner_voter_history;
+        }
+        checkpoint_voting_power(
+            new_owner_history,
+            &mut ctx.accounts.total_voting_power,
+            new_owner_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        emit!(StakeTransferredEvent {
+            from: ctx.accounts.user.key(),
+            to: new_owner,
+            amount,
+            locked_until: new_owner_stake.locked_until,
         });
         
         Ok(())
     }
-    
-    pub fn compound_rewards(ctx: Context<CompoundRewards>) -> Result<()> {
-        // Enforce pause
+
+    pub fn request_unstake(
+        ctx: Context<RequestUnstake>,
+        amount: u64,
+    ) -> Result<()> {
         require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        require!(amount > 0, StakingError::AmountTooLow);
         let user_stake = &mut ctx.accounts.user_stake;
         let clock = Clock::get()?;
         
-        // Calculate pending rewards
-        let pending_rewards = calculate_rewards(
-            user_stake.staked_amount,
-            get_tier_apy(user_stake.staked_amount)?,
-            user_stake.last_claim_timestamp,
-            clock.unix_timestamp,
-        )?;
-        
-        let total_unclaimed = user_stake.rewards_earned
-            .checked_add(pending_rewards).unwrap()
-            .checked_sub(user_stake.rewards_claimed).unwrap();
-        
-        require!(total_unclaimed > 0, StakingError::NoRewards);
-        
-        // Check escrow has sufficient balance
         require!(
-            ctx.accounts.reward_escrow.total_balance >= total_unclaimed,
-            StakingError::InsufficientEscrowBalance
+            clock.unix_timestamp >= user_stake.locked_until,
+            StakingError::TokensLocked
+        );
+        require!(
+            user_stake.staked_amount.checked_sub(user_stake.position_amount).unwrap() >= amount,
+            StakingError::InsufficientStake
         );
         
-        // Update stake amount by adding rewards
-        let old_staked = user_stake.staked_amount;
-        user_stake.staked_amount = user_stake.staked_amount
-            .checked_add(total_unclaimed).unwrap();
+        // Settle rewards up to now; the unbonding amount stops earning from here on
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
         
-        // Update tier based on new amount
-        let old_tier = user_stake.tier;
-        user_stake.tier = get_tier(user_stake.staked_amount)?;
+        reset_loyalty(user_stake, amount, clock.unix_timestamp)?;
+        user_stake.staked_amount = user_stake.staked_amount.checked_sub(amount).unwrap();
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
         
-        // Update reward tracking
-        user_stake.rewards_earned = user_stake.rewards_earned.checked_add(pending_rewards).unwrap();
-        user_stake.rewards_claimed = user_stake.rewards_earned; // Mark all as claimed since compounded
-        user_stake.last_claim_timestamp = clock.unix_timestamp;
+        let ticket = &mut ctx.accounts.unbonding_ticket;
+        ticket.owner = ctx.accounts.user.key();
+        ticket.index = user_stake.unbonding_count;
+        ticket.amount = amount;
+        ticket.requested_at = clock.unix_timestamp;
+        ticket.unlock_at = clock.unix_timestamp + program_state.unbonding_period;
+        ticket.bump = ctx.bumps.unbonding_ticket;
         
-        // Reduce escrow balance (rewards stay in vault as part of stake)
-        let escrow = &mut ctx.accounts.reward_escrow;
-        escrow.total_balance = escrow.total_balance.checked_sub(total_unclaimed).unwrap();
-        escrow.total_distributed = escrow.total_distributed.checked_add(total_unclaimed).unwrap();
+        user_stake.unbonding_count = user_stake.unbonding_count.checked_add(1).unwrap();
+        user_stake.unbonding_amount = user_stake.unbonding_amount.checked_add(amount).unwrap();
         
-        // Update global staked amount
-        let program_state = &mut ctx.accounts.program_state;
-        program_state.total_staked = program_state.total_staked.checked_add(total_unclaimed).unwrap();
+        // Move the amount from the active to the unbonding bucket
+        program_state.total_staked = program_state.total_staked.checked_sub(amount).unwrap();
+        program_state.total_unbonding = program_state.total_unbonding.checked_add(amount).unwrap();
         
-        msg!(
-            "Compounded {} rewards. Stake: {} -> {}, Tier: {} -> {}",
-            total_unclaimed,
-            old_staked,
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
             user_stake.staked_amount,
-            old_tier,
-            user_stake.tier
-        );
+            clock.slot,
+        )?;
         
-        emit!(RewardsCompoundedEvent {
+        emit!(UnbondingRequestedEvent {
             user: ctx.accounts.user.key(),
-            amount_compounded: total_unclaimed,
-            new_stake_amount: user_stake.staked_amount,
-            old_tier,
+            ticket_index: ticket.index,
+            amount,
+            unlock_at: ticket.unlock_at,
+            remaining_stake: user_stake.staked_amount,
             new_tier: user_stake.tier,
-            timestamp: clock.unix_timestamp,
         });
         
         Ok(())
     }
-}
-
-// Account structures
-#[account]
-pub struct ProgramState {
-    pub authority: Pubkey,
-    pub defai_mint: Pubkey,
-    pub total_staked: u64,
-    pub total_users: u64,
-    pub paused: bool,
-    pub vault_bump: u8,              // Bump for stake-vault PDA
-    pub reward_escrow_bump: u8,      // Bump for reward-escrow PDA
-    pub escrow_vault_bump: u8,       // Bump for escrow-vault PDA (token account)
-    pub pending_authority: Option<Pubkey>,
-    pub authority_change_timestamp: i64,
-}
-
-#[account]
-pub struct RewardEscrow {
-    pub authority: Pubkey,
-    pub total_balance: u64,
+    
+    pub fn withdraw_unbonded(
+        ctx: Context<WithdrawUnbonded>,
+        ticket_index: u64,
+    ) -> Result<()> {
+        // Emergency mode releases tickets even while paused and before the cooldown ends
+        let emergency_mode = ctx.accounts.program_state.emergency_mode;
+        require!(!ctx.accounts.program_state.paused || emergency_mode, StakingError::ProgramPaused);
+        let ticket = &ctx.accounts.unbonding_ticket;
+        let clock = Clock::get()?;
+        
+        require!(
+            emergency_mode || clock.unix_timestamp >= ticket.unlock_at,
+            StakingError::UnbondingNotComplete
+        );
+        
+        // Penalty-free: the cooldown replaces the early-unstake penalty
+        let amount = ticket.amount;
+        let program_state_key = ctx.accounts.program_state.key();
+        let seeds = &[
+            b"stake-vault",
+            program_state_key.as_ref(),
+            &[ctx.accounts.program_state.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.stake_vault.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.stake_vault.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            signer,
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        
+        let user_stake = &mut ctx.accounts.user_stake;
+        user_stake.unbonding_amount = user_stake.unbonding_amount.checked_sub(amount).unwrap();
+        
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_unbonding = program_state.total_unbonding.checked_sub(amount).unwrap();
+        
+        emit!(UnbondedWithdrawnEvent {
+            user: ctx.accounts.user.key(),
+            ticket_index,
+            amount,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn cancel_unbonding(
+        ctx: Context<CancelUnbonding>,
+        ticket_index: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let user_stake = &mut ctx.accounts.user_stake;
+        let amount = ctx.accounts.unbonding_ticket.amount;
+        let clock = Clock::get()?;
+        
+        // Settle at the old share count, then put the amount back to work
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
+        
+        user_stake.staked_amount = user_stake.staked_amount.checked_add(amount).unwrap();
+        user_stake.unbonding_amount = user_stake.unbonding_amount.checked_sub(amount).unwrap();
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        
+        program_state.total_unbonding = program_state.total_unbonding.checked_sub(amount).unwrap();
+        program_state.total_staked = program_state.total_staked.checked_add(amount).unwrap();
+        
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        emit!(UnbondingCancelledEvent {
+            user: ctx.accounts.user.key(),
+            ticket_index,
+            amount,
+            new_stake_amount: user_stake.staked_amount,
+            new_tier: user_stake.tier,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn update_unbonding_period(
+        ctx: Context<UpdateUnbondingPeriod>,
+        unbonding_period: i64,
+    ) -> Result<()> {
+        // Only affects tickets requested from now on
+        let old_period = apply_unbonding_period(&mut ctx.accounts.program_state, unbonding_period)?;
+        
+        emit!(UnbondingPeriodUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            old_period,
+            new_period: unbonding_period,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+        // Enforce pause
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+        
+        // Settle pending rewards from the global index
+        update_reward_index(&mut ctx.accounts.program_state, clock.unix_timestamp)?;
+        settle_rewards(&mut ctx.accounts.program_state, user_stake)?;
+        
+        let total_claimable = user_stake.rewards_earned
+            .checked_sub(user_stake.rewards_claimed).unwrap();
+        
+        require!(total_claimable > 0, StakingError::NoRewards);
+        
+        // Check escrow has sufficient balance
+        require!(
+            ctx.accounts.reward_escrow.total_balance >= total_claimable,
+            StakingError::InsufficientEscrowBalance
+        );
+        
+        // Transfer rewards from escrow to user
+        let program_state_key = ctx.accounts.program_state.key();
+        let escrow_seeds = &[
+            b"reward-escrow",
+            program_state_key.as_ref(),
+            &[ctx.accounts.program_state.reward_escrow_bump],
+        ];
+        let escrow_signer = &[&escrow_seeds[..]];
+        
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.reward_escrow.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            escrow_signer,
+        );
+        transfer_checked(transfer_ctx, total_claimable, ctx.accounts.defai_mint.decimals)?;
+        
+        // Update user stake
+        user_stake.rewards_claimed = user_stake.rewards_claimed.checked_add(total_claimable).unwrap();
+        user_stake.last_claim_timestamp = clock.unix_timestamp;
+        
+        // Paid rewards are no longer an outstanding liability
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(total_claimable);
+        
+        // Pick up any tier table changes since the user's last interaction
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        
+        // Update escrow
+        let escrow = &mut ctx.accounts.reward_escrow;
+        escrow.total_balance = escrow.total_balance.checked_sub(total_claimable).unwrap();
+        escrow.total_distributed = escrow.total_distributed.checked_add(total_claimable).unwrap();
+        
+        emit!(RewardsClaimedEvent {
+            user: ctx.accounts.user.key(),
+            amount: total_claimable,
+            total_distributed: escrow.total_distributed,
+            loyalty_bonus_bps: user_stake.loyalty_bonus_bps,
+        });
+        
+        Ok(())
+    }
+
+    // One per referrer; referees name the referrer on their first stake
+    pub fn initialize_referral_account(ctx: Context<InitializeReferralAccount>) -> Result<()> {
+        let referral_account = &mut ctx.accounts.referral_account;
+        referral_account.referrer = ctx.accounts.referrer.key();
+        referral_account.referred_count = 0;
+        referral_account.referred_volume = 0;
+        referral_account.rewards_accrued = 0;
+        referral_account.rewards_claimed = 0;
+        referral_account.bump = ctx.bumps.referral_account;
+        
+        Ok(())
+    }
+    
+    // Share of referees' rewards paid to referrers on top, not deducted from the referee
+    pub fn set_referral_bps(ctx: Context<UpdateRewardRate>, referral_bps: u16) -> Result<()> {
+        apply_referral_bps(&mut ctx.accounts.program_state, referral_bps)?;
+        
+        emit!(ReferralSettingsUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            referral_bps,
+        });
+        
+        Ok(())
+    }
+    
+    // remaining_accounts holds (UserStake, ReferralLink) pairs for the referees to accrue from.
+    // Settles each referee and moves their referral_rewards_owed to the referrer, then pays as
+    // much of the referrer's unpaid balance as the escrow surplus covers. The rest stays on
+    // total_referral_liability until a later claim.
+    pub fn claim_referral_rewards<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimReferralRewards<'info>>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(pairs.remainder().is_empty(), StakingError::InvalidReferralAccounts);
+        let clock = Clock::get()?;
+        
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        let referrer = ctx.accounts.referrer.key();
+        
+        let mut seen: Vec<Pubkey> = Vec::new();
+        let mut accrued: u64 = 0;
+        for pair in pairs {
+            let mut user_stake = Account::<UserStake>::try_from(&pair[0])?;
+            let link = Account::<ReferralLink>::try_from(&pair[1])?;
+            require_keys_eq!(link.referrer, referrer, StakingError::InvalidReferralAccounts);
+            require_keys_eq!(user_stake.owner, link.referee, StakingError::InvalidReferralAccounts);
+            require!(!seen.contains(&link.key()), StakingError::InvalidReferralAccounts);
+            seen.push(link.key());
+            
+            // The liability was already counted when the referee's rewards were settled
+            settle_rewards(program_state, &mut user_stake)?;
+            accrued = accrued.checked_add(user_stake.referral_rewards_owed).unwrap();
+            user_stake.referral_rewards_owed = 0;
+            user_stake.exit(ctx.program_id)?;
+        }
+        
+        let referral_account = &mut ctx.accounts.referral_account;
+        referral_account.rewards_accrued = referral_account.rewards_accrued.checked_add(accrued).unwrap();
+        let owed = referral_account.rewards_accrued
+            .checked_sub(referral_account.rewards_claimed)
+            .unwrap();
+        require!(owed > 0, StakingError::NoRewards);
+        
+        // Referral rewards are paid on top of staking rewards, so they come from the surplus above
+        // total_pending_rewards and what other referrers are still owed
+        let others_owed = program_state.total_referral_liability.saturating_sub(owed);
+        let amount = ctx.accounts.reward_escrow.total_balance
+            .saturating_sub(program_state.total_pending_rewards)
+            .saturating_sub(others_owed)
+            .min(owed);
+        if amount == 0 {
+            msg!("Accrued {} referral rewards; escrow surplus is empty, nothing paid", accrued);
+            return Ok(());
+        }
+        
+        let program_state_key = program_state.key();
+        let escrow_seeds = &[
+            b"reward-escrow",
+            program_state_key.as_ref(),
+            &[program_state.reward_escrow_bump],
+        ];
+        let escrow_signer = &[&escrow_seeds[..]];
+        
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.referrer_token_account.to_account_info(),
+                authority: ctx.accounts.reward_escrow.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            escrow_signer,
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        
+        referral_account.rewards_claimed = referral_account.rewards_claimed.checked_add(amount).unwrap();
+        program_state.total_referral_liability = program_state.total_referral_liability.saturating_sub(amount);
+        let escrow = &mut ctx.accounts.reward_escrow;
+        escrow.total_balance = escrow.total_balance.checked_sub(amount).unwrap();
+        escrow.total_distributed = escrow.total_distributed.checked_add(amount).unwrap();
+        
+        emit!(ReferralRewardsClaimedEvent {
+            referrer,
+            amount,
+            referees: seen.len() as u64,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // Pays out any claimable DEFAI rewards and closes an empty stake back to its owner
+    pub fn close_user_stake(ctx: Context<CloseUserStake>) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+        
+        require!(user_stake.staked_amount == 0, StakingError::StakeNotEmpty);
+        require!(user_stake.unbonding_amount == 0, StakingError::OpenUnbondingTickets);
+        
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
+        require!(
+            user_stake.stream_rewards_owed.iter().all(|owed| *owed == 0),
+            StakingError::UnclaimedStreamRewards
+        );
+        
+        // Hand the referrer what this stake still owes them; the liability stays counted
+        if user_stake.referral_rewards_owed > 0 {
+            match (ctx.accounts.referral_link.as_ref(), ctx.accounts.referral_account.as_mut()) {
+                (Some(link), Some(referral_account)) => {
+                    require_keys_eq!(link.referrer, referral_account.referrer, StakingError::InvalidReferralAccounts);
+                    referral_account.rewards_accrued = referral_account.rewards_accrued
+                        .checked_add(user_stake.referral_rewards_owed)
+                        .unwrap();
+                    user_stake.referral_rewards_owed = 0;
+                }
+                _ => return err!(StakingError::InvalidReferralAccounts),
+            }
+        }
+        
+        let claimable = user_stake.rewards_earned.checked_sub(user_stake.rewards_claimed).unwrap();
+        if claimable > 0 {
+            require!(
+                ctx.accounts.reward_escrow.total_balance >= claimable,
+                StakingError::InsufficientEscrowBalance
+            );
+            
+            let program_state_key = ctx.accounts.program_state.key();
+            let escrow_seeds = &[
+                b"reward-escrow",
+                program_state_key.as_ref(),
+                &[ctx.accounts.program_state.reward_escrow_bump],
+            ];
+            let escrow_signer = &[&escrow_seeds[..]];
+            
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow_token_account.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.reward_escrow.to_account_info(),
+                    mint: ctx.accounts.defai_mint.to_account_info(),
+                },
+                escrow_signer,
+            );
+            transfer_checked(transfer_ctx, claimable, ctx.accounts.defai_mint.decimals)?;
+            
+            let escrow = &mut ctx.accounts.reward_escrow;
+            escrow.total_balance = escrow.total_balance.checked_sub(claimable).unwrap();
+            escrow.total_distributed = escrow.total_distributed.checked_add(claimable).unwrap();
+        }
+        
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(claimable);
+        program_state.total_reward_shares = program_state.total_reward_shares
+            .checked_sub(ctx.accounts.user_stake.reward_shares)
+            .unwrap();
+        program_state.total_users = program_state.total_users.saturating_sub(1);
+        
+        // UserStake account is closed back to the user by the `close` constraint
+        emit!(UserStakeClosedEvent {
+            user: ctx.accounts.user.key(),
+            rewards_paid: claimable,
+            swept: false,
+        });
+        
+        Ok(())
+    }
+    
+    // Authority batch-close of dormant empty stakes: remaining_accounts holds (UserStake, owner)
+    // pairs and each stake's rent goes back to its owner. Stakes with anything left to pay out
+    // must be closed by the owner with close_user_stake instead.
+    pub fn sweep_dormant_stakes<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepDormantStakes<'info>>,
+    ) -> Result<()> {
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(pairs.remainder().is_empty(), StakingError::InvalidSweepAccounts);
+        let clock = Clock::get()?;
+        
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        
+        let mut closed: u64 = 0;
+        for pair in pairs {
+            let mut user_stake = Account::<UserStake>::try_from(&pair[0])?;
+            let owner_info = &pair[1];
+            require_keys_eq!(owner_info.key(), user_stake.owner, StakingError::InvalidOwner);
+            
+            let last_activity = user_stake.last_stake_timestamp.max(user_stake.last_claim_timestamp);
+            require!(
+                clock.unix_timestamp >= last_activity + DORMANT_STAKE_PERIOD,
+                StakingError::StakeNotDormant
+            );
+            require!(user_stake.staked_amount == 0, StakingError::StakeNotEmpty);
+            require!(user_stake.unbonding_amount == 0, StakingError::OpenUnbondingTickets);
+            
+            settle_rewards(program_state, &mut user_stake)?;
+            require!(
+                user_stake.rewards_earned == user_stake.rewards_claimed
+                    && user_stake.stream_rewards_owed.iter().all(|owed| *owed == 0)
+                    && user_stake.referral_rewards_owed == 0,
+                StakingError::StakeNotEmpty
+            );
+            
+            program_state.total_reward_shares = program_state.total_reward_shares
+                .checked_sub(user_stake.reward_shares)
+                .unwrap();
+            program_state.total_users = program_state.total_users.saturating_sub(1);
+            
+            emit!(UserStakeClosedEvent {
+                user: user_stake.owner,
+                rewards_paid: 0,
+                swept: true,
+            });
+            
+            // Zeroes the account, so a duplicate entry fails to deserialize
+            user_stake.close(owner_info.clone())?;
+            closed += 1;
+        }
+        
+        emit!(DormantStakesSweptEvent {
+            authority: ctx.accounts.authority.key(),
+            closed,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    // Rewrites the original, unversioned ProgramState into the current layout. Fields the
+    // old layout didn't have get the same defaults as initialize_program. `legacy_pending_rewards`
+    // is the off-chain total of what unmigrated stakes are owed under the old linear APY; it
+    // seeds total_pending_rewards so escrow withdrawals can't touch it before every stake migrates.
+    pub fn migrate_program_state(
+        ctx: Context<MigrateProgramState>,
+        legacy_pending_rewards: u64,
+    ) -> Result<()> {
+        let info = ctx.accounts.program_state.to_account_info();
+        let legacy = read_legacy_account::<LegacyProgramState>(
+            &info,
+            ctx.program_id,
+            &ProgramState::DISCRIMINATOR,
+            LEGACY_PROGRAM_STATE_LEN,
+            ProgramState::LEN,
+        )?;
+        require_keys_eq!(legacy.authority, ctx.accounts.authority.key(), StakingError::InvalidAuthority);
+        let clock = Clock::get()?;
+        
+        let program_state = ProgramState {
+            authority: legacy.authority,
+            defai_mint: legacy.defai_mint,
+            total_staked: legacy.total_staked,
+            total_users: legacy.total_users,
+            paused: legacy.paused,
+            vault_bump: legacy.vault_bump,
+            reward_escrow_bump: legacy.reward_escrow_bump,
+            escrow_vault_bump: legacy.escrow_vault_bump,
+            pending_authority: legacy.pending_authority,
+            authority_change_timestamp: legacy.authority_change_timestamp,
+            acc_reward_per_share: 0,
+            last_reward_timestamp: clock.unix_timestamp,
+            reward_rate: 0, // Set via update_reward_rate once stakes are migrated
+            total_reward_shares: 0, // Rebuilt as each UserStake is migrated
+            total_pending_rewards: legacy_pending_rewards,
+            unbonding_period: DEFAULT_UNBONDING_PERIOD,
+            total_unbonding: 0,
+            liquid_reward_shares: 0,
+            reward_streams: [RewardStream::default(); MAX_REWARD_STREAMS],
+            epoch_duration: 0,
+            current_epoch: 0,
+            epoch_start_timestamp: 0,
+            epoch_reward_budget: 0,
+            next_epoch_reward_budget: 0,
+            paused_at: if legacy.paused { clock.unix_timestamp } else { 0 },
+            emergency_mode: false,
+            emergency_mode_timestamp: 0,
+            compound_interval: 24 * 60 * 60, // Daily
+            compound_tip_bps: 0,
+            version: PROGRAM_STATE_VERSION,
+            escrow_funding_limit: 0,
+            referral_bps: 0,
+            loyalty_steps: [LoyaltyStep::default(); MAX_LOYALTY_STEPS],
+            loyalty_cap_bps: 0,
+            legacy_pending_rewards,
+            total_referral_liability: 0,
+            reserved: [0; 4],
+        };
+        
+        write_migrated_account(
+            &info,
+            &program_state,
+            8 + ProgramState::LEN,
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program,
+        )?;
+        
+        emit!(AccountMigratedEvent {
+            account: info.key(),
+            version: PROGRAM_STATE_VERSION,
+            payer: ctx.accounts.authority.key(),
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn migrate_reward_escrow(ctx: Context<MigrateRewardEscrow>) -> Result<()> {
+        let info = ctx.accounts.reward_escrow.to_account_info();
+        let legacy = read_legacy_account::<LegacyRewardEscrow>(
+            &info,
+            ctx.program_id,
+            &RewardEscrow::DISCRIMINATOR,
+            LEGACY_REWARD_ESCROW_LEN,
+            RewardEscrow::LEN,
+        )?;
+        
+        let reward_escrow = RewardEscrow {
+            authority: legacy.authority,
+            total_balance: legacy.total_balance,
+            total_distributed: legacy.total_distributed,
+            bump: legacy.bump,
+            version: REWARD_ESCROW_VERSION,
+            safety_buffer_bps: 0,
+            pending_withdrawal: 0,
+            withdrawal_unlock_timestamp: 0,
+            reserved: [0; 14],
+        };
+        
+        write_migrated_account(
+            &info,
+            &reward_escrow,
+            8 + RewardEscrow::LEN,
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program,
+        )?;
+        
+        emit!(AccountMigratedEvent {
+            account: info.key(),
+            version: REWARD_ESCROW_VERSION,
+            payer: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // Rewrites an original, unversioned UserStake into the current layout and gives it
+    // reward shares and a voting power checkpoint. Paid for by the owner or the authority.
+    // Legacy APY rewards up to now are settled into rewards_earned; rewards accrue under the
+    // reward index from the migration onwards.
+    pub fn migrate_user_stake(ctx: Context<MigrateUserStake>) -> Result<()> {
+        require!(
+            ctx.accounts.payer.key() == ctx.accounts.owner.key()
+                || ctx.accounts.payer.key() == ctx.accounts.program_state.authority,
+            StakingError::InvalidAuthority
+        );
+        
+        let info = ctx.accounts.user_stake.to_account_info();
+        let legacy = read_legacy_account::<LegacyUserStake>(
+            &info,
+            ctx.program_id,
+            &UserStake::DISCRIMINATOR,
+            LEGACY_USER_STAKE_LEN,
+            UserStake::LEN,
+        )?;
+        require_keys_eq!(legacy.owner, ctx.accounts.owner.key(), StakingError::InvalidOwner);
+        let clock = Clock::get()?;
+        
+        let accrued = legacy_accrued_rewards(&legacy, clock.unix_timestamp)?;
+        let rewards_earned = legacy.rewards_earned.checked_add(accrued).unwrap();
+        let outstanding = rewards_earned.saturating_sub(legacy.rewards_claimed);
+        
+        let mut user_stake = UserStake {
+            owner: legacy.owner,
+            staked_amount: legacy.staked_amount,
+            rewards_earned,
+            rewards_claimed: legacy.rewards_claimed,
+            tier: legacy.tier,
+            stake_timestamp: legacy.stake_timestamp,
+            last_stake_timestamp: legacy.last_stake_timestamp,
+            last_claim_timestamp: clock.unix_timestamp,
+            locked_until: legacy.locked_until,
+            reward_shares: 0,
+            reward_debt: 0,
+            position_count: 0,
+            position_amount: 0,
+            position_bonus_amount: 0,
+            unbonding_count: 0,
+            unbonding_amount: 0,
+            stream_reward_debts: [0; MAX_REWARD_STREAMS],
+            stream_rewards_owed: [0; MAX_REWARD_STREAMS],
+            auto_compound: false,
+            last_auto_compound_timestamp: 0,
+            version: USER_STAKE_VERSION,
+            loyalty_bonus_bps: 0,
+            referred: false,
+            referral_rewards_owed: 0,
+            reserved: [0; 21],
+        };
+        
+        // The seeded legacy total already covers this stake's outstanding rewards; anything
+        // beyond it becomes a new liability
+        let program_state = &mut ctx.accounts.program_state;
+        let covered = outstanding.min(program_state.legacy_pending_rewards);
+        program_state.legacy_pending_rewards -= covered;
+        program_state.total_pending_rewards = program_state.total_pending_rewards
+            .checked_add(outstanding - covered)
+            .unwrap();
+        
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        update_reward_shares(program_state, &ctx.accounts.tier_config, &mut user_stake, clock.unix_timestamp)?;
+        
+        let voter_history = &mut ctx.accounts.voter_history;
+        if voter_history.owner == Pubkey::default() {
+            voter_history.owner = legacy.owner;
+            voter_history.bump = ctx.bumps.voter_history;
+        }
+        checkpoint_voting_power(
+            voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        write_migrated_account(
+            &info,
+            &user_stake,
+            8 + UserStake::LEN,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+        
+        emit!(AccountMigratedEvent {
+            account: info.key(),
+            version: USER_STAKE_VERSION,
+            payer: ctx.accounts.payer.key(),
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    pub fn propose_authority_change(
+        ctx: Context<UpdateAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.pending_authority = Some(new_authority);
+        program_state.authority_change_timestamp = Clock::get()?.unix_timestamp + ADMIN_TIMELOCK_DURATION;
+        
+        emit!(AuthorityUpdatedEvent {
+            old_authority: program_state.authority,
+            new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        msg!("Authority change proposed. Can be executed after {}", program_state.authority_change_timestamp);
+        
+        Ok(())
+    }
+    
+    pub fn accept_authority_change(
+        ctx: Context<UpdateAuthority>,
+    ) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        
+        require!(
+            program_state.pending_authority.is_some(),
+            StakingError::NoPendingAuthorityChange
+        );
+        require!(
+            Clock::get()?.unix_timestamp >= program_state.authority_change_timestamp,
+            StakingError::TimelockNotExpired
+        );
+        
+        let old_authority = program_state.authority;
+        let new_authority = program_state.pending_authority.unwrap();
+        program_state.authority = new_authority;
+        program_state.pending_authority = None;
+        program_state.authority_change_timestamp = 0;
+        
+        emit!(AuthorityUpdatedEvent {
+            old_authority,
+            new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        msg!("Authority changed from {} to {}", old_authority, new_authority);
+        
+        Ok(())
+    }
+
+    // Creates the M-of-N council. It only acts once it holds the authority: hand it over with
+    // propose_authority_change(council PDA) and accept_authority_change after the timelock.
+    pub fn initialize_council(
+        ctx: Context<InitializeCouncil>,
+        members: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        validate_council(&members, threshold)?;
+        
+        let council = &mut ctx.accounts.council;
+        council.program_state = ctx.accounts.program_state.key();
+        council.members = members;
+        council.threshold = threshold;
+        council.proposal_count = 0;
+        council.bump = ctx.bumps.council;
+        
+        emit!(CouncilUpdatedEvent {
+            council: council.key(),
+            members: council.members.clone(),
+            threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // The proposer's approval counts towards the threshold
+    pub fn propose_council_action(
+        ctx: Context<ProposeCouncilAction>,
+        action: CouncilAction,
+    ) -> Result<()> {
+        let council = &mut ctx.accounts.council;
+        let proposer = ctx.accounts.proposer.key();
+        require!(council.members.contains(&proposer), StakingError::NotCouncilMember);
+        validate_council_action(&action)?;
+        let clock = Clock::get()?;
+        
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.council = council.key();
+        proposal.id = council.proposal_count;
+        proposal.proposer = proposer;
+        proposal.action = action;
+        proposal.approvals = vec![proposer];
+        proposal.executable_at = 0;
+        proposal.bump = ctx.bumps.proposal;
+        if count_approvals(council, proposal) >= council.threshold as usize {
+            proposal.executable_at = clock.unix_timestamp + ADMIN_TIMELOCK_DURATION;
+        }
+        
+        council.proposal_count = council.proposal_count.checked_add(1).unwrap();
+        
+        emit!(CouncilProposalCreatedEvent {
+            council: council.key(),
+            proposal_id: proposal.id,
+            proposer,
+            action: proposal.action.clone(),
+            executable_at: proposal.executable_at,
+        });
+        
+        Ok(())
+    }
+    
+    // The 48h timelock starts when the threshold is reached
+    pub fn approve_council_proposal(ctx: Context<ApproveCouncilProposal>) -> Result<()> {
+        let council = &ctx.accounts.council;
+        let member = ctx.accounts.member.key();
+        require!(council.members.contains(&member), StakingError::NotCouncilMember);
+        
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.approvals.contains(&member), StakingError::AlreadyApproved);
+        proposal.approvals.push(member);
+        
+        let approvals = count_approvals(council, proposal);
+        if proposal.executable_at == 0 && approvals >= council.threshold as usize {
+            proposal.executable_at = Clock::get()?.unix_timestamp + ADMIN_TIMELOCK_DURATION;
+        }
+        
+        emit!(CouncilProposalApprovedEvent {
+            council: council.key(),
+            proposal_id: proposal.id,
+            member,
+            approvals: approvals as u8,
+            executable_at: proposal.executable_at,
+        });
+        
+        Ok(())
+    }
+    
+    // Permissionless once approved and timelocked. Approvals are recounted against the
+    // current member set, so approvals from removed members no longer count.
+    pub fn execute_council_proposal(ctx: Context<ExecuteCouncilProposal>) -> Result<()> {
+        let clock = Clock::get()?;
+        let council = &mut ctx.accounts.council;
+        let proposal = &ctx.accounts.proposal;
+        require!(
+            proposal.executable_at != 0
+                && count_approvals(council, proposal) >= council.threshold as usize,
+            StakingError::ProposalNotApproved
+        );
+        require!(clock.unix_timestamp >= proposal.executable_at, StakingError::TimelockNotExpired);
+        
+        let council_key = council.key();
+        let program_state = &mut ctx.accounts.program_state;
+        match proposal.action.clone() {
+            CouncilAction::Pause { paused } => {
+                set_paused(program_state, paused, clock.unix_timestamp);
+                emit!(ProgramPausedEvent {
+                    authority: council_key,
+                    paused,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::UpdateTiers { tiers } => {
+                validate_tiers(&tiers)?;
+                let tier_config = &mut ctx.accounts.tier_config;
+                tier_config.tiers = tiers;
+                emit!(TierConfigUpdatedEvent {
+                    authority: council_key,
+                    tiers: tier_config.tiers.clone(),
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::SetEscrowFundingLimit { limit } => {
+                program_state.escrow_funding_limit = limit;
+                emit!(EscrowFundingLimitUpdatedEvent {
+                    authority: council_key,
+                    limit,
+                });
+            }
+            CouncilAction::RotateAuthority { new_authority } => {
+                program_state.authority = new_authority;
+                program_state.pending_authority = None;
+                program_state.authority_change_timestamp = 0;
+                emit!(AuthorityUpdatedEvent {
+                    old_authority: council_key,
+                    new_authority,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::UpdateCouncil { members, threshold } => {
+                validate_council(&members, threshold)?;
+                council.members = members;
+                council.threshold = threshold;
+                emit!(CouncilUpdatedEvent {
+                    council: council_key,
+                    members: council.members.clone(),
+                    threshold,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::SetRewardRate { reward_rate } => {
+                let old_rate = apply_reward_rate(program_state, reward_rate, clock.unix_timestamp)?;
+                emit!(RewardRateUpdatedEvent {
+                    authority: council_key,
+                    old_rate,
+                    new_rate: reward_rate,
+                    acc_reward_per_share: program_state.acc_reward_per_share,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::ConfigureEmissionSchedule { epoch_duration, epoch_reward_budget } => {
+                apply_emission_schedule(program_state, epoch_duration, epoch_reward_budget, clock.unix_timestamp)?;
+                emit!(EmissionScheduleUpdatedEvent {
+                    authority: council_key,
+                    epoch: program_state.current_epoch,
+                    epoch_duration,
+                    epoch_reward_budget,
+                    reward_rate: program_state.reward_rate,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::SetNextEpochBudget { epoch_reward_budget } => {
+                apply_next_epoch_budget(program_state, epoch_reward_budget, clock.unix_timestamp)?;
+                emit!(NextEpochBudgetSetEvent {
+                    authority: council_key,
+                    current_epoch: program_state.current_epoch,
+                    next_epoch_reward_budget: epoch_reward_budget,
+                    next_epoch_start: program_state.epoch_start_timestamp
+                        .checked_add(program_state.epoch_duration)
+                        .unwrap(),
+                });
+            }
+            CouncilAction::UpdateRewardStream { stream_index, reward_rate, end_timestamp } => {
+                apply_reward_stream_update(program_state, stream_index, reward_rate, end_timestamp, clock.unix_timestamp)?;
+                emit!(RewardStreamUpdatedEvent {
+                    authority: council_key,
+                    stream_index,
+                    reward_rate,
+                    end_timestamp,
+                });
+            }
+            CouncilAction::SetUnbondingPeriod { unbonding_period } => {
+                let old_period = apply_unbonding_period(program_state, unbonding_period)?;
+                emit!(UnbondingPeriodUpdatedEvent {
+                    authority: council_key,
+                    old_period,
+                    new_period: unbonding_period,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::SetReferralBps { referral_bps } => {
+                apply_referral_bps(program_state, referral_bps)?;
+                emit!(ReferralSettingsUpdatedEvent {
+                    authority: council_key,
+                    referral_bps,
+                });
+            }
+            CouncilAction::UpdateCompoundSettings { compound_interval, compound_tip_bps } => {
+                apply_compound_settings(program_state, compound_interval, compound_tip_bps)?;
+                emit!(CompoundSettingsUpdatedEvent {
+                    authority: council_key,
+                    compound_interval,
+                    compound_tip_bps,
+                });
+            }
+            CouncilAction::UpdateLoyaltyConfig { steps, cap_bps } => {
+                apply_loyalty_config(program_state, &steps, cap_bps)?;
+                emit!(LoyaltyConfigUpdatedEvent {
+                    authority: council_key,
+                    steps,
+                    cap_bps,
+                });
+            }
+            CouncilAction::UpdatePenaltyConfig { steps, destination, treasury } => {
+                let penalty_config = &mut ctx.accounts.penalty_config;
+                apply_penalty_config(penalty_config, steps, destination, treasury)?;
+                emit!(PenaltyConfigUpdatedEvent {
+                    authority: council_key,
+                    steps: penalty_config.steps.clone(),
+                    destination,
+                    treasury,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            CouncilAction::SetEscrowSafetyBuffer { safety_buffer_bps } => {
+                ctx.accounts.reward_escrow.safety_buffer_bps = safety_buffer_bps;
+                emit!(EscrowSafetyBufferUpdatedEvent {
+                    authority: council_key,
+                    safety_buffer_bps,
+                });
+            }
+            CouncilAction::ProposeEmergencyMode => {
+                apply_emergency_mode_proposal(program_state, clock.unix_timestamp)?;
+                emit!(EmergencyModeProposedEvent {
+                    authority: council_key,
+                    activates_at: program_state.emergency_mode_timestamp,
+                });
+            }
+        }
+        
+        emit!(CouncilProposalExecutedEvent {
+            council: council_key,
+            proposal_id: proposal.id,
+            executor: ctx.accounts.executor.key(),
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn cancel_council_proposal(ctx: Context<CancelCouncilProposal>) -> Result<()> {
+        emit!(CouncilProposalCancelledEvent {
+            council: ctx.accounts.council.key(),
+            proposal_id: ctx.accounts.proposal.id,
+            proposer: ctx.accounts.proposer.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // Caps a single fund_escrow deposit; 0 = no limit
+    pub fn set_escrow_funding_limit(ctx: Context<UpdateRewardRate>, limit: u64) -> Result<()> {
+        ctx.accounts.program_state.escrow_funding_limit = limit;
+        
+        emit!(EscrowFundingLimitUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            limit,
+        });
+        
+        Ok(())
+    }
+
+    pub fn pause_program(ctx: Context<PauseProgram>, paused: bool) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        let clock = Clock::get()?;
+        
+        set_paused(program_state, paused, clock.unix_timestamp);
+        
+        emit!(ProgramPausedEvent {
+            authority: ctx.accounts.authority.key(),
+            paused,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn propose_emergency_mode(ctx: Context<PauseProgram>) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        apply_emergency_mode_proposal(program_state, Clock::get()?.unix_timestamp)?;
+        
+        emit!(EmergencyModeProposedEvent {
+            authority: ctx.accounts.authority.key(),
+            activates_at: program_state.emergency_mode_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // Permissionless: after the authority's timelock, or once the program has been
+    // paused for EMERGENCY_PAUSE_THRESHOLD. Emergency mode can't be switched off.
+    pub fn activate_emergency_mode(ctx: Context<ActivateEmergencyMode>) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        require!(!program_state.emergency_mode, StakingError::EmergencyModeActive);
+        
+        let clock = Clock::get()?;
+        let timelock_passed = program_state.emergency_mode_timestamp != 0
+            && clock.unix_timestamp >= program_state.emergency_mode_timestamp;
+        let pause_expired = program_state.paused
+            && program_state.paused_at != 0
+            && clock.unix_timestamp >= program_state.paused_at + EMERGENCY_PAUSE_THRESHOLD;
+        require!(timelock_passed || pause_expired, StakingError::EmergencyConditionsNotMet);
+        
+        program_state.emergency_mode = true;
+        
+        emit!(EmergencyModeActivatedEvent {
+            caller: ctx.accounts.caller.key(),
+            via_timelock: timelock_passed,
+            paused_at: program_state.paused_at,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // Returns the full staked_amount with no penalty and closes the stake. Unpaid rewards are
+    // forfeited. Every open StakePosition must be passed in remaining_accounts; they are closed too.
+    pub fn emergency_withdraw<'info>(
+        ctx: Context<'_, '_, 'info, 'info, EmergencyWithdraw<'info>>,
+    ) -> Result<()> {
+        require!(ctx.accounts.program_state.emergency_mode, StakingError::EmergencyModeNotActive);
+        let user_stake = &mut ctx.accounts.user_stake;
+        let user_key = ctx.accounts.user.key();
+        let clock = Clock::get()?;
+        
+        // Tickets are released immediately in emergency mode; withdraw them first
+        require!(user_stake.unbonding_amount == 0, StakingError::OpenUnbondingTickets);
+        
+        // Close all positions; their amounts must add up to the stake's position principal
+        let mut position_total: u64 = 0;
+        for account_info in ctx.remaining_accounts.iter() {
+            let position = Account::<StakePosition>::try_from(account_info)?;
+            require_keys_eq!(position.owner, user_key, StakingError::InvalidOwner);
+            position_total = position_total.checked_add(position.amount).unwrap();
+            // Closing zeroes the account, so a duplicate entry fails to deserialize
+            position.close(ctx.accounts.user.to_account_info())?;
+        }
+        require!(
+            position_total == user_stake.position_amount,
+            StakingError::InvalidPositionAccounts
+        );
+        
+        let amount = user_stake.staked_amount;
+        
+        // Settle so the forfeited rewards can be dropped from the outstanding liabilities
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        settle_rewards(program_state, user_stake)?;
+        let forfeited_rewards = user_stake.rewards_earned.saturating_sub(user_stake.rewards_claimed);
+        program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(forfeited_rewards);
+        // The referrer's share of the forfeited rewards goes with them
+        program_state.total_referral_liability = program_state.total_referral_liability
+            .saturating_sub(user_stake.referral_rewards_owed);
+        user_stake.referral_rewards_owed = 0;
+        for (slot, stream) in program_state.reward_streams.iter_mut().enumerate() {
+            stream.total_pending_rewards = stream.total_pending_rewards
+                .saturating_sub(user_stake.stream_rewards_owed[slot]);
+        }
+        
+        // Drop the stake's reward shares
+        user_stake.staked_amount = 0;
+        user_stake.position_amount = 0;
+        user_stake.position_bonus_amount = 0;
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        
+        program_state.total_staked = program_state.total_staked.checked_sub(amount).unwrap();
+        program_state.total_users = program_state.total_users.saturating_sub(1);
+        
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        if amount > 0 {
+            let program_state_key = ctx.accounts.program_state.key();
+            let seeds = &[
+                b"stake-vault",
+                program_state_key.as_ref(),
+                &[ctx.accounts.program_state.vault_bump],
+            ];
+            let signer = &[&seeds[..]];
+            
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.stake_vault.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.stake_vault.to_account_info(),
+                    mint: ctx.accounts.defai_mint.to_account_info(),
+                },
+                signer,
+            );
+            transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        }
+        
+        // UserStake account is closed back to the user by the `close` constraint
+        emit!(EmergencyWithdrawEvent {
+            user: user_key,
+            amount,
+            forfeited_rewards,
+            positions_closed: ctx.remaining_accounts.len() as u64,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn update_reward_rate(ctx: Context<UpdateRewardRate>, reward_rate: u64) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        let clock = Clock::get()?;
+        let old_rate = apply_reward_rate(program_state, reward_rate, clock.unix_timestamp)?;
+        
+        emit!(RewardRateUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            old_rate,
+            new_rate: reward_rate,
+            acc_reward_per_share: program_state.acc_reward_per_share,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // Start a new emission epoch now; epoch_duration = 0 turns the schedule off
+    pub fn configure_emission_schedule(
+        ctx: Context<UpdateRewardRate>,
+        epoch_duration: i64,
+        epoch_reward_budget: u64,
+    ) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        let clock = Clock::get()?;
+        apply_emission_schedule(program_state, epoch_duration, epoch_reward_budget, clock.unix_timestamp)?;
+        
+        emit!(EmissionScheduleUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            epoch: program_state.current_epoch,
+            epoch_duration,
+            epoch_reward_budget,
+            reward_rate: program_state.reward_rate,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    // Budget for the epochs after the current one; the current epoch keeps its budget
+    pub fn set_next_epoch_budget(
+        ctx: Context<UpdateRewardRate>,
+        epoch_reward_budget: u64,
+    ) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        apply_next_epoch_budget(program_state, epoch_reward_budget, Clock::get()?.unix_timestamp)?;
+        
+        emit!(NextEpochBudgetSetEvent {
+            authority: ctx.accounts.authority.key(),
+            current_epoch: program_state.current_epoch,
+            next_epoch_reward_budget: epoch_reward_budget,
+            next_epoch_start: program_state.epoch_start_timestamp
+                .checked_add(program_state.epoch_duration)
+                .unwrap(),
+        });
+        
+        Ok(())
+    }
+    
+    // Read-only: returns an EscrowRunway via return data
+    pub fn get_escrow_runway(ctx: Context<GetEscrowRunway>) -> Result<()> {
+        let clock = Clock::get()?;
+        
+        // Project accruals up to now without writing them back
+        let mut program_state = (*ctx.accounts.program_state).clone();
+        update_reward_index(&mut program_state, clock.unix_timestamp)?;
+        
+        let escrow_balance = ctx.accounts.reward_escrow.total_balance;
+        let total_liabilities = program_state.total_pending_rewards;
+        let exhausted_at = project_escrow_exhaustion(
+            &program_state,
+            escrow_balance.saturating_sub(total_liabilities),
+            clock.unix_timestamp,
+        );
+        
+        let runway = EscrowRunway {
+            total_liabilities,
+            escrow_balance,
+            reward_rate: program_state.reward_rate,
+            current_epoch: program_state.current_epoch,
+            exhausted_at,
+            timestamp: clock.unix_timestamp,
+        };
+        set_return_data(&runway.try_to_vec()?);
+        
+        Ok(())
+    }
+    
+    pub fn initialize_voting_power(ctx: Context<InitializeVotingPower>) -> Result<()> {
+        let total_voting_power = &mut ctx.accounts.total_voting_power;
+        total_voting_power.program_state = ctx.accounts.program_state.key();
+        total_voting_power.history = CheckpointRing::default();
+        total_voting_power.bump = ctx.bumps.total_voting_power;
+        
+        Ok(())
+    }
+    
+    // Read-only, CPI-callable: returns a VotingPowerAt via return data
+    pub fn get_voting_power_at(ctx: Context<GetVotingPowerAt>, slot: u64) -> Result<()> {
+        // Checkpoints for the current slot can still change within it
+        require!(slot < Clock::get()?.slot, StakingError::InvalidCheckpointSlot);
+        
+        let voter_history = &ctx.accounts.voter_history;
+        let voting_power_at = VotingPowerAt {
+            owner: voter_history.owner,
+            slot,
+            voting_power: voter_history.history.value_at(slot, MAX_VOTER_CHECKPOINTS)?,
+            total_voting_power: ctx.accounts.total_voting_power.history
+                .value_at(slot, MAX_TOTAL_CHECKPOINTS)?,
+        };
+        set_return_data(&voting_power_at.try_to_vec()?);
+        
+        Ok(())
+    }
+    
+    // CPI-friendly tier lookup, see tier_cpi. The tier is computed against the current tier
+    // table, and wallets without a stake come back as tier 0.
+    pub fn get_user_tier(ctx: Context<GetUserTier>) -> Result<()> {
+        let clock = Clock::get()?;
+        let owner = ctx.accounts.owner.key();
+        
+        let info = &ctx.accounts.user_stake;
+        let (staked_amount, locked_until) = if info.data_is_empty() {
+            (0, 0)
+        } else {
+            require_keys_eq!(*info.owner, crate::ID, StakingError::InvalidOwner);
+            let user_stake = UserStake::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            require_keys_eq!(user_stake.owner, owner, StakingError::InvalidOwner);
+            (user_stake.staked_amount, user_stake.locked_until)
+        };
+        
+        let user_tier = UserTier {
+            owner,
+            tier: get_tier(&ctx.accounts.tier_config, staked_amount),
+            staked_amount,
+            locked_until,
+            slot: clock.slot,
+        };
+        set_return_data(&user_tier.try_to_vec()?);
+        
+        Ok(())
+    }
+    
+    pub fn initialize_penalty_config(
+        ctx: Context<InitializePenaltyConfig>,
+        steps: Vec<PenaltyStep>,
+        destination: PenaltyDestination,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        validate_penalty_steps(&steps)?;
+        
+        let penalty_config = &mut ctx.accounts.penalty_config;
+        penalty_config.program_state = ctx.accounts.program_state.key();
+        penalty_config.steps = steps;
+        penalty_config.destination = destination;
+        penalty_config.treasury = treasury;
+        penalty_config.bump = ctx.bumps.penalty_config;
+        
+        emit!(PenaltyConfigUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            steps: penalty_config.steps.clone(),
+            destination,
+            treasury,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn update_penalty_config(
+        ctx: Context<UpdatePenaltyConfig>,
+        steps: Vec<PenaltyStep>,
+        destination: PenaltyDestination,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        let penalty_config = &mut ctx.accounts.penalty_config;
+        apply_penalty_config(penalty_config, steps, destination, treasury)?;
+        
+        emit!(PenaltyConfigUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            steps: penalty_config.steps.clone(),
+            destination,
+            treasury,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn initialize_tier_config(
+        ctx: Context<InitializeTierConfig>,
+        tiers: Vec<TierInfo>,
+    ) -> Result<()> {
+        validate_tiers(&tiers)?;
+        
+        let tier_config = &mut ctx.accounts.tier_config;
+        tier_config.program_state = ctx.accounts.program_state.key();
+        tier_config.tiers = tiers;
+        tier_config.pending_tiers = None;
+        tier_config.tier_change_timestamp = 0;
+        tier_config.bump = ctx.bumps.tier_config;
+        
+        emit!(TierConfigUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            tiers: tier_config.tiers.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn propose_tier_config(
+        ctx: Context<UpdateTierConfig>,
+        tiers: Vec<TierInfo>,
+    ) -> Result<()> {
+        validate_tiers(&tiers)?;
+        
+        let tier_config = &mut ctx.accounts.tier_config;
+        tier_config.tier_change_timestamp = Clock::get()?.unix_timestamp + ADMIN_TIMELOCK_DURATION;
+        
+        emit!(TierConfigProposedEvent {
+            authority: ctx.accounts.authority.key(),
+            tiers: tiers.clone(),
+            executable_at: tier_config.tier_change_timestamp,
+        });
+        
+        tier_config.pending_tiers = Some(tiers);
+        
+        msg!("Tier config change proposed. Can be executed after {}", tier_config.tier_change_timestamp);
+        
+        Ok(())
+    }
+    
+    pub fn accept_tier_config(ctx: Context<UpdateTierConfig>) -> Result<()> {
+        let tier_config = &mut ctx.accounts.tier_config;
+        
+        require!(
+            tier_config.pending_tiers.is_some(),
+            StakingError::NoPendingTierChange
+        );
+        require!(
+            Clock::get()?.unix_timestamp >= tier_config.tier_change_timestamp,
+            StakingError::TimelockNotExpired
+        );
+        
+        // Existing stakes pick up the new table lazily on their next interaction
+        tier_config.tiers = tier_config.pending_tiers.take().unwrap();
+        tier_config.tier_change_timestamp = 0;
+        
+        emit!(TierConfigUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            tiers: tier_config.tiers.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn compound_rewards(ctx: Context<CompoundRewards>) -> Result<()> {
+        // Enforce pause
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+        
+        // Settle pending rewards from the global index
+        update_reward_index(&mut ctx.accounts.program_state, clock.unix_timestamp)?;
+        settle_rewards(&mut ctx.accounts.program_state, user_stake)?;
+        
+        let total_unclaimed = user_stake.rewards_earned
+            .checked_sub(user_stake.rewards_claimed).unwrap();
+        
+        require!(total_unclaimed > 0, StakingError::NoRewards);
+        
+        // Check escrow has sufficient balance
+        require!(
+            ctx.accounts.reward_escrow.total_balance >= total_unclaimed,
+            StakingError::InsufficientEscrowBalance
+        );
+        
+        // Move the rewards into the stake vault so the compounded principal is backed by tokens
+        let program_state_key = ctx.accounts.program_state.key();
+        let escrow_seeds = &[
+            b"reward-escrow",
+            program_state_key.as_ref(),
+            &[ctx.accounts.program_state.reward_escrow_bump],
+        ];
+        let escrow_signer = &[&escrow_seeds[..]];
+        
+        let vault_balance_before = ctx.accounts.stake_vault.amount;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.stake_vault.to_account_info(),
+                authority: ctx.accounts.reward_escrow.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            escrow_signer,
+        );
+        transfer_checked(transfer_ctx, total_unclaimed, ctx.accounts.defai_mint.decimals)?;
+        let credited = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
+        
+        // Update stake amount by adding what actually reached the vault
+        let old_staked = user_stake.staked_amount;
+        user_stake.staked_amount = user_stake.staked_amount
+            .checked_add(credited).unwrap();
+        
+        let old_tier = user_stake.tier;
+        
+        // Update reward tracking
+        user_stake.rewards_claimed = user_stake.rewards_earned; // Mark all as claimed since compounded
+        user_stake.last_claim_timestamp = clock.unix_timestamp;
+        
+        let escrow = &mut ctx.accounts.reward_escrow;
+        escrow.total_balance = escrow.total_balance.checked_sub(total_unclaimed).unwrap();
+        escrow.total_distributed = escrow.total_distributed.checked_add(total_unclaimed).unwrap();
+        
+        // Update global staked amount and reward shares
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_staked = program_state.total_staked.checked_add(credited).unwrap();
+        program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(total_unclaimed);
+        update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+        
+        checkpoint_voting_power(
+            &mut ctx.accounts.voter_history,
+            &mut ctx.accounts.total_voting_power,
+            user_stake.staked_amount,
+            clock.slot,
+        )?;
+        
+        msg!(
+            "Compounded {} rewards. Stake: {} -> {}, Tier: {} -> {}",
+            credited,
+            old_staked,
+            user_stake.staked_amount,
+            old_tier,
+            user_stake.tier
+        );
+        
+        emit!(RewardsCompoundedEvent {
+            user: ctx.accounts.user.key(),
+            amount_compounded: credited,
+            new_stake_amount: user_stake.staked_amount,
+            old_tier,
+            new_tier: user_stake.tier,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn set_auto_compound(ctx: Context<SetAutoCompound>, enabled: bool) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+        user_stake.auto_compound = enabled;
+        
+        emit!(AutoCompoundToggledEvent {
+            user: ctx.accounts.user.key(),
+            enabled,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn update_compound_settings(
+        ctx: Context<UpdateRewardRate>,
+        compound_interval: i64,
+        compound_tip_bps: u16,
+    ) -> Result<()> {
+        apply_compound_settings(&mut ctx.accounts.program_state, compound_interval, compound_tip_bps)?;
+        
+        emit!(CompoundSettingsUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            compound_interval,
+            compound_tip_bps,
+        });
+        
+        Ok(())
+    }
+    
+    // Existing stakes pick up the new boosts on their next interaction
+    pub fn update_loyalty_config(
+        ctx: Context<UpdateRewardRate>,
+        steps: Vec<LoyaltyStep>,
+        cap_bps: u16,
+    ) -> Result<()> {
+        apply_loyalty_config(&mut ctx.accounts.program_state, &steps, cap_bps)?;
+        
+        emit!(LoyaltyConfigUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            steps,
+            cap_bps,
+        });
+        
+        Ok(())
+    }
+    
+    // Permissionless: compounds `user` and any further (UserStake, VoterHistory) pairs passed in
+    // remaining_accounts. Stakes that haven't opted in, are inside the interval or have nothing
+    // to compound are skipped. The keeper receives compound_tip_bps of what was compounded.
+    pub fn crank_compound<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CrankCompound<'info>>,
+        user: Pubkey,
+    ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(pairs.remainder().is_empty(), StakingError::InvalidCrankAccounts);
+        let clock = Clock::get()?;
+        
+        // Batched stakes; every UserStake may appear only once
+        let mut seen = vec![ctx.accounts.user_stake.key()];
+        let mut batch: Vec<(Account<'info, UserStake>, Account<'info, VoterHistory>)> = Vec::new();
+        for pair in pairs {
+            let user_stake = Account::<UserStake>::try_from(&pair[0])?;
+            let voter_history = Account::<VoterHistory>::try_from(&pair[1])?;
+            require!(!seen.contains(&user_stake.key()), StakingError::InvalidCrankAccounts);
+            require_keys_eq!(voter_history.owner, user_stake.owner, StakingError::InvalidCrankAccounts);
+            seen.push(user_stake.key());
+            batch.push((user_stake, voter_history));
+        }
+        
+        let mut targets: Vec<(&mut UserStake, &mut VoterHistory)> =
+            vec![(&mut ctx.accounts.user_stake, &mut ctx.accounts.voter_history)];
+        for (user_stake, voter_history) in batch.iter_mut() {
+            targets.push((user_stake, voter_history));
+        }
+        
+        // Settle and take each eligible stake's unclaimed rewards
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        let mut amounts: Vec<(u64, u64)> = Vec::with_capacity(targets.len());
+        let mut total_compound: u64 = 0;
+        let mut total_tip: u64 = 0;
+        for (user_stake, _) in targets.iter_mut() {
+            let due = user_stake.last_auto_compound_timestamp
+                .checked_add(program_state.compound_interval)
+                .ok_or(StakingError::MathOverflow)?;
+            if !user_stake.auto_compound || clock.unix_timestamp < due {
+                amounts.push((0, 0));
+                continue;
+            }
+            
+            settle_rewards(program_state, user_stake)?;
+            let unclaimed = user_stake.rewards_earned.checked_sub(user_stake.rewards_claimed).unwrap();
+            if unclaimed == 0 {
+                amounts.push((0, 0));
+                continue;
+            }
+            
+            user_stake.rewards_claimed = user_stake.rewards_earned;
+            user_stake.last_claim_timestamp = clock.unix_timestamp;
+            user_stake.last_auto_compound_timestamp = clock.unix_timestamp;
+            
+            let tip = (unclaimed as u128 * program_state.compound_tip_bps as u128 / BASIS_POINTS as u128) as u64;
+            let compound = unclaimed - tip;
+            total_compound = total_compound.checked_add(compound).unwrap();
+            total_tip = total_tip.checked_add(tip).unwrap();
+            amounts.push((compound, tip));
+        }
+        
+        let total_paid = total_compound.checked_add(total_tip).unwrap();
+        require!(total_paid > 0, StakingError::NoRewards);
+        require!(
+            ctx.accounts.reward_escrow.total_balance >= total_paid,
+            StakingError::InsufficientEscrowBalance
+        );
+        
+        let program_state_key = ctx.accounts.program_state.key();
+        let escrow_seeds = &[
+            b"reward-escrow",
+            program_state_key.as_ref(),
+            &[ctx.accounts.program_state.reward_escrow_bump],
+        ];
+        let escrow_signer = &[&escrow_seeds[..]];
+        
+        // Move the compounded rewards into the stake vault in one transfer
+        let vault_balance_before = ctx.accounts.stake_vault.amount;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.stake_vault.to_account_info(),
+                authority: ctx.accounts.reward_escrow.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            escrow_signer,
+        );
+        transfer_checked(transfer_ctx, total_compound, ctx.accounts.defai_mint.decimals)?;
+        let total_received = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
+        
+        if total_tip > 0 {
+            let tip_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow_token_account.to_account_info(),
+                    to: ctx.accounts.keeper_token_account.to_account_info(),
+                    authority: ctx.accounts.reward_escrow.to_account_info(),
+                    mint: ctx.accounts.defai_mint.to_account_info(),
+                },
+                escrow_signer,
+            );
+            transfer_checked(tip_ctx, total_tip, ctx.accounts.defai_mint.decimals)?;
+        }
+        
+        let escrow = &mut ctx.accounts.reward_escrow;
+        escrow.total_balance = escrow.total_balance.checked_sub(total_paid).unwrap();
+        escrow.total_distributed = escrow.total_distributed.checked_add(total_paid).unwrap();
+        
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(total_paid);
+        
+        // Credit each stake; a transfer fee is shared pro-rata
+        for ((user_stake, voter_history), (compound, tip)) in targets.iter_mut().zip(amounts) {
+            if compound == 0 && tip == 0 {
+                continue;
+            }
+            let credited = if total_compound == 0 {
+                0
+            } else {
+                (compound as u128 * total_received as u128 / total_compound as u128) as u64
+            };
+            
+            user_stake.staked_amount = user_stake.staked_amount.checked_add(credited).unwrap();
+            update_reward_shares(program_state, &ctx.accounts.tier_config, user_stake, clock.unix_timestamp)?;
+            program_state.total_staked = program_state.total_staked.checked_add(credited).unwrap();
+            
+            checkpoint_voting_power(
+                voter_history,
+                &mut ctx.accounts.total_voting_power,
+                user_stake.staked_amount,
+                clock.slot,
+            )?;
+            
+            emit!(AutoCompoundedEvent {
+                user: user_stake.owner,
+                keeper: ctx.accounts.keeper.key(),
+                amount_compounded: credited,
+                tip,
+                new_stake_amount: user_stake.staked_amount,
+                new_tier: user_stake.tier,
+                timestamp: clock.unix_timestamp,
+            });
+        }
+        drop(targets);
+        
+        // Batched accounts aren't persisted by Anchor; write them back explicitly
+        for (user_stake, voter_history) in batch.iter() {
+            user_stake.exit(ctx.program_id)?;
+            voter_history.exit(ctx.program_id)?;
+        }
+        
+        msg!("Auto-compounded {} stakes starting at {}", seen.len(), user);
+        
+        Ok(())
+    }
+    
+    pub fn initialize_liquid_pool(ctx: Context<InitializeLiquidPool>) -> Result<()> {
+        let pool = &mut ctx.accounts.liquid_pool;
+        pool.program_state = ctx.accounts.program_state.key();
+        pool.st_mint = ctx.accounts.st_mint.key();
+        pool.total_underlying = 0;
+        pool.reward_shares = 0;
+        pool.reward_debt = 0;
+        pool.unharvested_rewards = 0;
+        pool.bump = ctx.bumps.liquid_pool;
+        pool.st_mint_bump = ctx.bumps.st_mint;
+        
+        msg!("Liquid pool initialized with stDEFAI mint {}", pool.st_mint);
+        
+        Ok(())
+    }
+    
+    pub fn stake_liquid(
+        ctx: Context<StakeLiquid>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.program_state.paused, StakingError::ProgramPaused);
+        require!(amount > 0, StakingError::AmountTooLow);
+        let clock = Clock::get()?;
+        
+        // Compound the pool's accrued rewards from escrow into the stake vault first,
+        // so the exchange rate is current before any stDEFAI is minted or burned
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        let harvested = settle_liquid_pool(
+            program_state,
+            &mut ctx.accounts.liquid_pool,
+            ctx.accounts.reward_escrow.total_balance,
+        )?;
+        
+        if harvested > 0 {
+            let program_state_key = ctx.accounts.program_state.key();
+            let escrow_seeds = &[
+                b"reward-escrow",
+                program_state_key.as_ref(),
+                &[ctx.accounts.program_state.reward_escrow_bump],
+            ];
+            let escrow_signer = &[&escrow_seeds[..]];
+            
+            let vault_balance_before = ctx.accounts.stake_vault.amount;
+            let harvest_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow_token_account.to_account_info(),
+                    to: ctx.accounts.stake_vault.to_account_info(),
+                    authority: ctx.accounts.reward_escrow.to_account_info(),
+                    mint: ctx.accounts.defai_mint.to_account_info(),
+                },
+                escrow_signer,
+            );
+            transfer_checked(harvest_ctx, harvested, ctx.accounts.defai_mint.decimals)?;
+            let harvest_received = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
+            
+            let escrow = &mut ctx.accounts.reward_escrow;
+            escrow.total_balance = escrow.total_balance.checked_sub(harvested).unwrap();
+            escrow.total_distributed = escrow.total_distributed.checked_add(harvested).unwrap();
+            
+            let program_state = &mut ctx.accounts.program_state;
+            program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(harvested);
+            program_state.total_staked = program_state.total_staked.checked_add(harvest_received).unwrap();
+            
+            let pool = &mut ctx.accounts.liquid_pool;
+            pool.total_underlying = pool.total_underlying.checked_add(harvest_received).unwrap();
+        }
+        
+        let vault_balance_before = ctx.accounts.stake_vault.amount;
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.stake_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        // Transfer-fee mints deliver less than `amount`; only what arrived backs new stDEFAI
+        let amount = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
+        
+        // Mint stDEFAI at the current exchange rate
+        let st_supply = ctx.accounts.st_mint.supply;
+        let st_amount = liquid_to_mint(amount, ctx.accounts.liquid_pool.total_underlying, st_supply)?;
+        require!(st_amount > 0, StakingError::AmountTooLow);
+        
+        let program_state_key = ctx.accounts.program_state.key();
+        let pool_seeds = &[
+            b"liquid-pool",
+            program_state_key.as_ref(),
+            &[ctx.accounts.liquid_pool.bump],
+        ];
+        let pool_signer = &[&pool_seeds[..]];
+        
+        let mint_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.st_mint.to_account_info(),
+                to: ctx.accounts.user_st_account.to_account_info(),
+                authority: ctx.accounts.liquid_pool.to_account_info(),
+            },
+            pool_signer,
+        );
+        mint_to(mint_ctx, st_amount)?;
+        
+        let program_state = &mut ctx.accounts.program_state;
+        let pool = &mut ctx.accounts.liquid_pool;
+        pool.total_underlying = pool.total_underlying.checked_add(amount).unwrap();
+        update_liquid_pool_shares(program_state, pool)?;
+        program_state.total_staked = program_state.total_staked.checked_add(amount).unwrap();
+        
+        emit!(LiquidStakeEvent {
+            user: ctx.accounts.user.key(),
+            amount,
+            st_amount,
+            total_underlying: pool.total_underlying,
+            st_supply: st_supply.checked_add(st_amount).unwrap(),
+        });
+        
+        Ok(())
+    }
+    
+    // Stays available while paused once emergency mode is on; the pool's unharvested
+    // rewards are then forfeited instead of compounded, like in emergency_withdraw
+    pub fn redeem_liquid(
+        ctx: Context<RedeemLiquid>,
+        st_amount: u64,
+    ) -> Result<()> {
+        let emergency_mode = ctx.accounts.program_state.emergency_mode;
+        require!(!ctx.accounts.program_state.paused || emergency_mode, StakingError::ProgramPaused);
+        require!(st_amount > 0, StakingError::AmountTooLow);
+        let clock = Clock::get()?;
+        
+        // Compound the pool's accrued rewards from escrow into the stake vault first,
+        // so the exchange rate is current before any stDEFAI is minted or burned
+        let program_state = &mut ctx.accounts.program_state;
+        update_reward_index(program_state, clock.unix_timestamp)?;
+        let escrow_balance = if emergency_mode { 0 } else { ctx.accounts.reward_escrow.total_balance };
+        let harvested = settle_liquid_pool(program_state, &mut ctx.accounts.liquid_pool, escrow_balance)?;
+        if emergency_mode {
+            let forfeited = std::mem::take(&mut ctx.accounts.liquid_pool.unharvested_rewards);
+            program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(forfeited);
+        }
+        
+        if harvested > 0 {
+            let program_state_key = ctx.accounts.program_state.key();
+            let escrow_seeds = &[
+                b"reward-escrow",
+                program_state_key.as_ref(),
+                &[ctx.accounts.program_state.reward_escrow_bump],
+            ];
+            let escrow_signer = &[&escrow_seeds[..]];
+            
+            let vault_balance_before = ctx.accounts.stake_vault.amount;
+            let harvest_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow_token_account.to_account_info(),
+                    to: ctx.accounts.stake_vault.to_account_info(),
+                    authority: ctx.accounts.reward_escrow.to_account_info(),
+                    mint: ctx.accounts.defai_mint.to_account_info(),
+                },
+                escrow_signer,
+            );
+            transfer_checked(harvest_ctx, harvested, ctx.accounts.defai_mint.decimals)?;
+            let harvest_received = received_amount(&mut ctx.accounts.stake_vault, vault_balance_before)?;
+            
+            let escrow = &mut ctx.accounts.reward_escrow;
+            escrow.total_balance = escrow.total_balance.checked_sub(harvested).unwrap();
+            escrow.total_distributed = escrow.total_distributed.checked_add(harvested).unwrap();
+            
+            let program_state = &mut ctx.accounts.program_state;
+            program_state.total_pending_rewards = program_state.total_pending_rewards.saturating_sub(harvested);
+            program_state.total_staked = program_state.total_staked.checked_add(harvest_received).unwrap();
+            
+            let pool = &mut ctx.accounts.liquid_pool;
+            pool.total_underlying = pool.total_underlying.checked_add(harvest_received).unwrap();
+        }
+        
+        // Underlying includes every reward compounded into the pool so far
+        let st_supply = ctx.accounts.st_mint.supply;
+        let amount = liquid_to_redeem(st_amount, ctx.accounts.liquid_pool.total_underlying, st_supply)?;
+        
+        let burn_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.st_mint.to_account_info(),
+                from: ctx.accounts.user_st_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        burn(burn_ctx, st_amount)?;
+        
+        let program_state_key = ctx.accounts.program_state.key();
+        let seeds = &[
+            b"stake-vault",
+            program_state_key.as_ref(),
+            &[ctx.accounts.program_state.vault_bump],
+        ];
+        let signer = &[&seeds[..]];
+        
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.stake_vault.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.stake_vault.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            signer,
+        );
+        transfer_checked(transfer_ctx, amount, ctx.accounts.defai_mint.decimals)?;
+        
+        let program_state = &mut ctx.accounts.program_state;
+        let pool = &mut ctx.accounts.liquid_pool;
+        pool.total_underlying = pool.total_underlying.checked_sub(amount).unwrap();
+        update_liquid_pool_shares(program_state, pool)?;
+        program_state.total_staked = program_state.total_staked.checked_sub(amount).unwrap();
+        
+        emit!(LiquidRedeemEvent {
+            user: ctx.accounts.user.key(),
+            st_amount,
+            amount,
+            total_underlying: pool.total_underlying,
+            st_supply: st_supply.checked_sub(st_amount).unwrap(),
+        });
+        
+        Ok(())
+    }
+}
+
+// `#[account]` for the layouts that have a migrate instruction. A checked load of an account
+// still on its legacy layout (shorter than LEN) or at an older version fails with
+// AccountNotMigrated instead of Anchor's generic AccountDidNotDeserialize.
+macro_rules! versioned_account {
+    ($name:ident, $discriminator:expr) => {
+        impl anchor_lang::AccountSerialize for $name {
+            fn try_serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+                if writer.write_all(&$discriminator).is_err() {
+                    return Err(ErrorCode::AccountDidNotSerialize.into());
+                }
+                if AnchorSerialize::serialize(self, writer).is_err() {
+                    return Err(ErrorCode::AccountDidNotSerialize.into());
+                }
+                Ok(())
+            }
+        }
+
+        impl anchor_lang::AccountDeserialize for $name {
+            fn try_deserialize(buf: &mut &[u8]) -> Result<Self> {
+                if buf.len() < 8 {
+                    return Err(ErrorCode::AccountDiscriminatorNotFound.into());
+                }
+                if buf[..8] != $discriminator {
+                    return Err(error!(ErrorCode::AccountDiscriminatorMismatch).with_account_name(stringify!($name)));
+                }
+                require!(buf.len() >= 8 + $name::LEN, StakingError::AccountNotMigrated);
+                let account = Self::try_deserialize_unchecked(buf)?;
+                require!(account.is_current(), StakingError::AccountNotMigrated);
+                Ok(account)
+            }
+
+            // Also used by `init`, which loads the freshly zeroed account
+            fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+                let mut data: &[u8] = &buf[8..];
+                AnchorDeserialize::deserialize(&mut data)
+                    .map_err(|_| ErrorCode::AccountDidNotDeserialize.into())
+            }
+        }
+
+        impl anchor_lang::Discriminator for $name {
+            const DISCRIMINATOR: [u8; 8] = $discriminator;
+        }
+
+        impl anchor_lang::Owner for $name {
+            fn owner() -> Pubkey {
+                crate::ID
+            }
+        }
+    };
+}
+
+// Account structures
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProgramState {
+    pub authority: Pubkey,
+    pub defai_mint: Pubkey,
+    pub total_staked: u64,
+    pub total_users: u64,
+    pub paused: bool,
+    pub vault_bump: u8,              // Bump for stake-vault PDA
+    pub reward_escrow_bump: u8,      // Bump for reward-escrow PDA
+    pub escrow_vault_bump: u8,       // Bump for escrow-vault PDA (token account)
+    pub pending_authority: Option<Pubkey>,
+    pub authority_change_timestamp: i64,
+    pub acc_reward_per_share: u128,  // Rewards per reward share, scaled by ACC_REWARD_PRECISION
+    pub last_reward_timestamp: i64,  // Last time acc_reward_per_share was advanced
+    pub reward_rate: u64,            // Reward tokens emitted per second across all shares
+    pub total_reward_shares: u64,    // Sum of all UserStake.reward_shares
+    pub total_pending_rewards: u64,  // Accrued but not yet paid out rewards
+    pub unbonding_period: i64,       // Cooldown between request_unstake and withdraw_unbonded
+    pub total_unbonding: u64,        // Principal sitting in unbonding tickets (excluded from total_staked)
+    pub liquid_reward_shares: u64,   // LiquidPool's part of total_reward_shares (DEFAI rewards only)
+    pub reward_streams: [RewardStream; MAX_REWARD_STREAMS],
+    pub epoch_duration: i64,            // Emission epoch length; 0 = no schedule, reward_rate set directly
+    pub current_epoch: u64,
+    pub epoch_start_timestamp: i64,
+    pub epoch_reward_budget: u64,       // Tokens emitted over the current epoch
+    pub next_epoch_reward_budget: u64,  // Applied at each epoch rollover
+    pub paused_at: i64,                 // When the current pause started; 0 when not paused
+    pub emergency_mode: bool,           // One-way switch enabling emergency_withdraw
+    pub emergency_mode_timestamp: i64,  // Proposed emergency mode activates at; 0 = not proposed
+    pub compound_interval: i64,         // Minimum time between crank_compound runs per stake
+    pub compound_tip_bps: u16,          // Keeper's cut of auto-compounded rewards
+    pub version: u8,                    // Layout version, see PROGRAM_STATE_VERSION
+    pub escrow_funding_limit: u64,      // Max single fund_escrow deposit; 0 = no limit
+    pub referral_bps: u16,              // Referrer's share of referee rewards, paid on top
+    pub loyalty_steps: [LoyaltyStep; MAX_LOYALTY_STEPS],  // Unused slots have days = 0
+    pub loyalty_cap_bps: u16,
+    pub legacy_pending_rewards: u64,    // Seeded legacy liability not yet claimed by a migrated stake
+    pub total_referral_liability: u64,  // Referral rewards accrued but not yet paid out
+    pub reserved: [u8; 4],              // Room for future fields without a realloc
+}
+
+impl ProgramState {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 1 + 1 + 1 + 1 + 33 + 8
+        + 16 + 8 + 8 + 8 + 8    // Reward index
+        + 8 + 8                 // Unbonding
+        + 8                     // Liquid pool shares
+        + MAX_REWARD_STREAMS * RewardStream::LEN
+        + 8 + 8 + 8 + 8 + 8     // Emission schedule
+        + 8 + 1 + 8             // Emergency mode
+        + 8 + 2                 // Auto-compound
+        + 1                     // Version
+        + 8                     // Escrow funding limit
+        + 2                     // Referral bps
+        + MAX_LOYALTY_STEPS * LoyaltyStep::LEN + 2  // Loyalty boost
+        + 8                     // Legacy pending rewards
+        + 8                     // Referral liability
+        + 4;                    // Reserved
+
+    pub fn is_current(&self) -> bool {
+        self.version == PROGRAM_STATE_VERSION
+    }
+}
+
+versioned_account!(ProgramState, [77, 209, 137, 229, 149, 67, 167, 230]);
+
+// Returned by get_escrow_runway
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EscrowRunway {
+    pub total_liabilities: u64,   // Accrued, unpaid DEFAI rewards
+    pub escrow_balance: u64,
+    pub reward_rate: u64,
+    pub current_epoch: u64,
+    pub exhausted_at: i64,        // Projected escrow exhaustion; i64::MAX if emission stops first
+    pub timestamp: i64,
+}
+
+// Additional reward token distributed over the same reward shares as DEFAI rewards
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RewardStream {
+    pub reward_mint: Pubkey,          // Pubkey::default() marks an unused slot
+    pub reward_rate: u64,             // Reward tokens per second
+    pub end_timestamp: i64,           // Emission stops here
+    pub acc_reward_per_share: u128,
+    pub last_reward_timestamp: i64,
+    pub total_balance: u64,           // Funded and not yet paid out
+    pub total_distributed: u64,
+    pub total_pending_rewards: u64,   // Accrued but not yet paid out
+    pub vault_bump: u8,
+}
+
+impl RewardStream {
+    pub const LEN: usize = 32 + 8 + 8 + 16 + 8 + 8 + 8 + 8 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RewardEscrow {
+    pub authority: Pubkey,
+    pub total_balance: u64,
+    pub total_distributed: u64,
+    pub bump: u8,
+    pub version: u8,          // Layout version, see REWARD_ESCROW_VERSION
+    pub safety_buffer_bps: u16,           // Kept above liabilities by withdraw_escrow
+    pub pending_withdrawal: u64,          // Proposed withdraw_escrow amount; 0 = none
+    pub withdrawal_unlock_timestamp: i64,
+    pub reserved: [u8; 14],
+}
+
+impl RewardEscrow {
+    pub const LEN: usize = 32 + 8 + 8 + 1
+        + 1                     // Version
+        + 2 + 8 + 8             // Withdrawal
+        + 14;                   // Reserved
+
+    pub fn is_current(&self) -> bool {
+        self.version == REWARD_ESCROW_VERSION
+    }
+}
+
+versioned_account!(RewardEscrow, [233, 153, 10, 144, 81, 195, 113, 5]);
+
+#[account]
+pub struct TierConfig {
+    pub program_state: Pubkey,
+    pub tiers: Vec<TierInfo>,                // Contiguous from 0 up to u64::MAX; index = tier id, tier 0 = unstaked
+    pub pending_tiers: Option<Vec<TierInfo>>,
+    pub tier_change_timestamp: i64,
+    pub bump: u8,
+}
+
+impl TierConfig {
+    pub const LEN: usize = 32 + (4 + MAX_TIERS * TierInfo::LEN) + (1 + 4 + MAX_TIERS * TierInfo::LEN) + 8 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TierInfo {
+    pub name: String,
+    pub min_amount: u64,
+    pub max_amount: u64,
+    pub multiplier_bps: u16,  // Reward share multiplier, 10_000 = 1x (replaces the old per-tier APY bps)
+    pub lock_days: u16,       // Lock applied when a stake lands in this tier
+}
+
+impl TierInfo {
+    pub const LEN: usize = (4 + MAX_TIER_NAME_LEN) + 8 + 8 + 2 + 2;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UserStake {
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub rewards_earned: u64,
+    pub rewards_claimed: u64,
+    pub tier: u8,
+    pub stake_timestamp: i64,         // Start of continuous staking; partial unstakes move it forward
+    pub last_stake_timestamp: i64,    // Most recent stake timestamp for penalty calculation
+    pub last_claim_timestamp: i64,
+    pub locked_until: i64,
+    pub reward_shares: u64,           // staked_amount weighted by tier multiplier
+    pub reward_debt: u128,            // reward_shares * acc_reward_per_share at last settlement
+    pub position_count: u64,          // Next StakePosition index
+    pub position_amount: u64,         // Portion of staked_amount held in StakePositions
+    pub position_bonus_amount: u64,   // Extra share weight from position lock multipliers
+    pub unbonding_count: u64,         // Next UnbondingTicket index
+    pub unbonding_amount: u64,        // Principal in this wallet's open UnbondingTickets
+    pub stream_reward_debts: [u128; MAX_REWARD_STREAMS],
+    pub stream_rewards_owed: [u64; MAX_REWARD_STREAMS],  // Settled, unclaimed stream rewards
+    pub auto_compound: bool,          // Opted in to permissionless crank_compound
+    pub last_auto_compound_timestamp: i64,
+    pub version: u8,                  // Layout version, see USER_STAKE_VERSION
+    pub loyalty_bonus_bps: u16,       // Loyalty boost applied to reward_shares
+    pub referred: bool,               // Has a ReferralLink; settlements accrue referral rewards
+    pub referral_rewards_owed: u64,   // Accrued to the referrer, not yet moved to their ReferralAccount
+    pub reserved: [u8; 21],
+}
+
+impl UserStake {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8
+        + 8 + 16                // Reward shares and debt
+        + 8 + 8 + 8             // Positions
+        + 8 + 8                 // Unbonding
+        + MAX_REWARD_STREAMS * (16 + 8)
+        + 1 + 8                 // Auto-compound
+        + 1                     // Version
+        + 2                     // Loyalty boost
+        + 1 + 8                 // Referral
+        + 21;                   // Reserved
+
+    pub fn is_current(&self) -> bool {
+        self.version == USER_STAKE_VERSION
+    }
+}
+
+versioned_account!(UserStake, [102, 53, 163, 107, 9, 138, 87, 153]);
+
+#[account]
+pub struct PenaltyConfig {
+    pub program_state: Pubkey,
+    pub steps: Vec<PenaltyStep>,          // Ascending by days; no step matched = no penalty
+    pub destination: PenaltyDestination,
+    pub treasury: Pubkey,                 // Token account receiving Treasury penalties
+    pub bump: u8,
+}
+
+impl PenaltyConfig {
+    pub const LEN: usize = 32 + 4 + MAX_PENALTY_STEPS * PenaltyStep::LEN + 1 + 32 + 1;
+}
+
+// Unstaking before `days` have passed costs `bps` of the amount
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PenaltyStep {
+    pub days: u16,
+    pub bps: u16,
+}
+
+impl PenaltyStep {
+    pub const LEN: usize = 2 + 2;
+}
+
+// Loyalty boost once a stake has been held continuously for `days`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct LoyaltyStep {
+    pub days: u16,
+    pub bonus_bps: u16,   // Added on top of the tier multiplier, 1_000 = +10%
+}
+
+impl LoyaltyStep {
+    pub const LEN: usize = 2 + 2;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PenaltyDestination {
+    Escrow,         // Added to the reward escrow balance
+    Redistribute,   // Paid to remaining stakers pro-rata through the reward index
+    Burn,
+    Treasury,
+}
+
+#[account]
+pub struct StakePosition {
+    pub owner: Pubkey,
+    pub index: u64,
+    pub amount: u64,
+    pub lock_days: u16,
+    pub multiplier_bps: u16,          // Lock multiplier applied on top of the tier multiplier
+    pub stake_timestamp: i64,         // Penalty clock for this position only
+    pub locked_until: i64,
+    pub bump: u8,
+}
+
+// Voting power (active staked_amount) history for one wallet
+#[account]
+pub struct VoterHistory {
+    pub owner: Pubkey,
+    pub history: CheckpointRing,
+    pub bump: u8,
+}
+
+impl VoterHistory {
+    pub const LEN: usize = 32 + CheckpointRing::space(MAX_VOTER_CHECKPOINTS) + 1;
+}
+
+// Sum of all wallets' voting power over time
+#[account]
+pub struct TotalVotingPower {
+    pub program_state: Pubkey,
+    pub history: CheckpointRing,
+    pub bump: u8,
+}
+
+impl TotalVotingPower {
+    pub const LEN: usize = 32 + CheckpointRing::space(MAX_TOTAL_CHECKPOINTS) + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Checkpoint {
+    pub slot: u64,
+    pub voting_power: u64,
+}
+
+// Fixed-capacity checkpoint history; once full the oldest entry is overwritten
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CheckpointRing {
+    pub next: u16,                    // Slot in `checkpoints` overwritten next once full
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl CheckpointRing {
+    pub const fn space(capacity: usize) -> usize {
+        2 + 4 + capacity * 16
+    }
+    
+    pub fn latest(&self) -> u64 {
+        let len = self.checkpoints.len();
+        if len == 0 {
+            return 0;
+        }
+        self.checkpoints[(self.next as usize + len - 1) % len].voting_power
+    }
+    
+    pub fn push(&mut self, slot: u64, voting_power: u64, capacity: usize) {
+        let len = self.checkpoints.len();
+        let checkpoint = Checkpoint { slot, voting_power };
+        
+        // Several changes in one slot collapse into one checkpoint
+        if len > 0 {
+            let latest = (self.next as usize + len - 1) % len;
+            if self.checkpoints[latest].slot == slot {
+                self.checkpoints[latest] = checkpoint;
+                return;
+            }
+        }
+        
+        if len < capacity {
+            self.checkpoints.push(checkpoint);
+            self.next = ((len + 1) % capacity) as u16;
+        } else {
+            self.checkpoints[self.next as usize] = checkpoint;
+            self.next = ((self.next as usize + 1) % capacity) as u16;
+        }
+    }
+    
+    // Voting power as of the end of `slot`
+    pub fn value_at(&self, slot: u64, capacity: usize) -> Result<u64> {
+        let found = self.checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.slot <= slot)
+            .max_by_key(|checkpoint| checkpoint.slot);
+        
+        match found {
+            Some(checkpoint) => Ok(checkpoint.voting_power),
+            // Nothing overwritten yet, so there was no stake before the first checkpoint
+            None if self.checkpoints.len() < capacity => Ok(0),
+            None => err!(StakingError::CheckpointTooOld),
+        }
+    }
+}
+
+// Returned by get_voting_power_at
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct VotingPowerAt {
+    pub owner: Pubkey,
+    pub slot: u64,
+    pub voting_power: u64,
+    pub total_voting_power: u64,
+}
+
+// Returned by get_user_tier
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UserTier {
+    pub owner: Pubkey,
+    pub tier: u8,                 // Index into TierConfig.tiers, 0 = unstaked band
+    pub staked_amount: u64,
+    pub locked_until: i64,
+    pub slot: u64,                // Slot the lookup ran in; informational only
+}
+
+#[account]
+pub struct LiquidPool {
+    pub program_state: Pubkey,
+    pub st_mint: Pubkey,
+    pub total_underlying: u64,        // DEFAI backing all stDEFAI, including compounded rewards
+    pub reward_shares: u64,           // Pool's weight in the global reward index (1x, no tier)
+    pub reward_debt: u128,
+    pub unharvested_rewards: u64,     // Accrued rewards the escrow could not cover yet
+    pub bump: u8,
+    pub st_mint_bump: u8,
+}
+
+#[account]
+pub struct UnbondingTicket {
+    pub owner: Pubkey,
+    pub index: u64,
+    pub amount: u64,
+    pub requested_at: i64,
+    pub unlock_at: i64,
+    pub bump: u8,
+}
+
+// Per-referrer totals; must exist before anyone can be referred to this wallet
+#[account]
+pub struct ReferralAccount {
+    pub referrer: Pubkey,
+    pub referred_count: u64,
+    pub referred_volume: u64,     // DEFAI staked through stake_tokens by referees
+    pub rewards_accrued: u64,
+    pub rewards_claimed: u64,
+    pub bump: u8,
+}
+
+impl ReferralAccount {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 1;
+}
+
+// Links a referee to their referrer, created on the referee's first stake
+#[account]
+pub struct ReferralLink {
+    pub referee: Pubkey,
+    pub referrer: Pubkey,
+    pub bump: u8,
+}
+
+impl ReferralLink {
+    pub const LEN: usize = 32 + 32 + 1;
+}
+
+// M-of-N signer set that can hold ProgramState.authority and act through timelocked proposals
+#[account]
+pub struct StakingCouncil {
+    pub program_state: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,          // Approvals needed before a proposal's timelock starts
+    pub proposal_count: u64,    // Next CouncilProposal id
+    pub bump: u8,
+}
+
+impl StakingCouncil {
+    pub const LEN: usize = 32 + (4 + MAX_COUNCIL_MEMBERS * 32) + 1 + 8 + 1;
+}
+
+#[account]
+pub struct CouncilProposal {
+    pub council: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: CouncilAction,
+    pub approvals: Vec<Pubkey>,
+    pub executable_at: i64,     // 0 until the threshold is reached
+    pub bump: u8,
+}
+
+impl CouncilProposal {
+    pub const LEN: usize = 32 + 8 + 32 + CouncilAction::MAX_LEN + (4 + MAX_COUNCIL_MEMBERS * 32) + 8 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum CouncilAction {
+    Pause { paused: bool },
+    UpdateTiers { tiers: Vec<TierInfo> },
+    SetEscrowFundingLimit { limit: u64 },
+    RotateAuthority { new_authority: Pubkey },
+    UpdateCouncil { members: Vec<Pubkey>, threshold: u8 },
+    // Operational settings the key authority would otherwise set directly
+    SetRewardRate { reward_rate: u64 },
+    ConfigureEmissionSchedule { epoch_duration: i64, epoch_reward_budget: u64 },
+    SetNextEpochBudget { epoch_reward_budget: u64 },
+    UpdateRewardStream { stream_index: u8, reward_rate: u64, end_timestamp: i64 },
+    SetUnbondingPeriod { unbonding_period: i64 },
+    SetReferralBps { referral_bps: u16 },
+    UpdateCompoundSettings { compound_interval: i64, compound_tip_bps: u16 },
+    UpdateLoyaltyConfig { steps: Vec<LoyaltyStep>, cap_bps: u16 },
+    UpdatePenaltyConfig { steps: Vec<PenaltyStep>, destination: PenaltyDestination, treasury: Pubkey },
+    SetEscrowSafetyBuffer { safety_buffer_bps: u16 },
+    ProposeEmergencyMode,
+}
+
+impl CouncilAction {
+    // UpdateTiers is the largest variant
+    pub const MAX_LEN: usize = 1 + 4 + MAX_TIERS * TierInfo::LEN;
+}
+
+// Original, unversioned layouts; only read by the migrate_* instructions
+#[derive(AnchorDeserialize)]
+pub struct LegacyProgramState {
+    pub authority: Pubkey,
+    pub defai_mint: Pubkey,
+    pub total_staked: u64,
+    pub total_users: u64,
+    pub paused: bool,
+    pub vault_bump: u8,
+    pub reward_escrow_bump: u8,
+    pub escrow_vault_bump: u8,
+    pub pending_authority: Option<Pubkey>,
+    pub authority_change_timestamp: i64,
+}
+
+#[derive(AnchorDeserialize)]
+pub struct LegacyRewardEscrow {
+    pub authority: Pubkey,
+    pub total_balance: u64,
     pub total_distributed: u64,
     pub bump: u8,
 }
 
-#[account]
-pub struct UserStake {
-    pub owner: Pubkey,
-    pub staked_amount: u64,
-    pub rewards_earned: u64,
-    pub rewards_claimed: u64,
-    pub tier: u8,
-    pub stake_timestamp: i64,         // Initial stake timestamp
-    pub last_stake_timestamp: i64,    // Most recent stake timestamp for penalty calculation
-    pub last_claim_timestamp: i64,
-    pub locked_until: i64,
+#[derive(AnchorDeserialize)]
+pub struct LegacyUserStake {
+    pub owner: Pubkey,
+    pub staked_amount: u64,
+    pub rewards_earned: u64,
+    pub rewards_claimed: u64,
+    pub tier: u8,
+    pub stake_timestamp: i64,
+    pub last_stake_timestamp: i64,
+    pub last_claim_timestamp: i64,
+    pub locked_until: i64,
+}
+
+// Context structs
+#[derive(Accounts)]
+pub struct InitializeProgram<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ProgramState::LEN,
+        seeds = [b"program-state"],
+        bump
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump,
+        token::mint = defai_mint,
+        token::authority = stake_vault,
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + RewardEscrow::LEN,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump,
+        token::mint = defai_mint,
+        token::authority = reward_escrow,
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct FundEscrow<'info> {
+    // Bring in ProgramState to access authoritative addresses
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        mut,
+        // Ensure reward_escrow is the correct PDA
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        // Ensure escrow_token_account is the correct ATA and is owned by reward_escrow
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint,  // Ensure the ATA's mint matches the provided mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub funder_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    
+    #[account(
+        // Ensure the provided mint is the official one from ProgramState
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ManageEscrow<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = defai_mint
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    pub authority: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AddRewardStream<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"stream-vault", program_state.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = reward_escrow,
+    )]
+    pub stream_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct FundRewardStream<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        mut,
+        seeds = [b"stream-vault", program_state.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+    )]
+    pub stream_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub funder_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    
+    #[account(
+        constraint = program_state.reward_streams
+            .get(stream_index as usize)
+            .map(|stream| stream.reward_mint) == Some(reward_mint.key()) @ StakingError::InvalidMint
+    )]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct ClaimStreamRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        seeds = [b"stream-vault", program_state.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+        token::authority = reward_escrow,
+        token::mint = reward_mint
+    )]
+    pub stream_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_reward_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = program_state.reward_streams
+            .get(stream_index as usize)
+            .map(|stream| stream.reward_mint) == Some(reward_mint.key()) @ StakingError::InvalidMint
+    )]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct StakeTokens<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStake::LEN,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VoterHistory::LEN,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    // Only needed when referred: created on the first stake
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + ReferralLink::LEN,
+        seeds = [b"referral-link", user.key().as_ref()],
+        bump
+    )]
+    pub referral_link: Option<Box<Account<'info, ReferralLink>>>,
+    
+    #[account(
+        mut,
+        seeds = [b"referral-account", referral_account.referrer.as_ref()],
+        bump = referral_account.bump
+    )]
+    pub referral_account: Option<Box<Account<'info, ReferralAccount>>>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(beneficiary: Pubkey)]
+pub struct StakeFor<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + UserStake::LEN,
+        seeds = [b"user-stake", beneficiary.as_ref()],
+        bump,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + VoterHistory::LEN,
+        seeds = [b"voter-history", beneficiary.as_ref()],
+        bump
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub payer_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeTokens<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"penalty-config", program_state.key().as_ref()],
+        bump = penalty_config.bump
+    )]
+    pub penalty_config: Box<Account<'info, PenaltyConfig>>,
+    
+    // Only needed when penalties go to the treasury
+    #[account(
+        mut,
+        constraint = treasury_token_account.key() == penalty_config.treasury @ StakingError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    
+    #[account(
+        mut,
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPosition<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStake::LEN,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VoterHistory::LEN,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 8 + 8 + 2 + 2 + 8 + 8 + 1,
+        seeds = [b"stake-position", user.key().as_ref(), &user_stake.position_count.to_le_bytes()],
+        bump
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_index: u64)]
+pub struct UnstakePosition<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"stake-position", user.key().as_ref(), &position_index.to_le_bytes()],
+        bump = stake_position.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"penalty-config", program_state.key().as_ref()],
+        bump = penalty_config.bump
+    )]
+    pub penalty_config: Box<Account<'info, PenaltyConfig>>,
+    
+    // Only needed when penalties go to the treasury
+    #[account(
+        mut,
+        constraint = treasury_token_account.key() == penalty_config.treasury @ StakingError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    
+    #[account(
+        mut,
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(position_index: u64, new_owner: Pubkey)]
+pub struct TransferStakePosition<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Box<Account<'info, ProgramState>>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Box<Account<'info, TierConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Box<Account<'info, UserStake>>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"stake-position", user.key().as_ref(), &position_index.to_le_bytes()],
+        bump = stake_position.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub stake_position: Box<Account<'info, StakePosition>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStake::LEN,
+        seeds = [b"user-stake", new_owner.as_ref()],
+        bump,
+    )]
+    pub new_owner_stake: Box<Account<'info, UserStake>>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 8 + 8 + 2 + 2 + 8 + 8 + 1,
+        seeds = [b"stake-position", new_owner.as_ref(), &new_owner_stake.position_count.to_le_bytes()],
+        bump
+    )]
+    pub new_position: Box<Account<'info, StakePosition>>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Box<Account<'info, VoterHistory>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VoterHistory::LEN,
+        seeds = [b"voter-history", new_owner.as_ref()],
+        bump
+    )]
+    pub new_owner_voter_history: Box<Account<'info, VoterHistory>>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Box<Account<'info, TotalVotingPower>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, new_owner: Pubkey)]
+pub struct TransferUserStake<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Box<Account<'info, ProgramState>>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Box<Account<'info, TierConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Box<Account<'info, UserStake>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStake::LEN,
+        seeds = [b"user-stake", new_owner.as_ref()],
+        bump,
+    )]
+    pub new_owner_stake: Box<Account<'info, UserStake>>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Box<Account<'info, VoterHistory>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VoterHistory::LEN,
+        seeds = [b"voter-history", new_owner.as_ref()],
+        bump
+    )]
+    pub new_owner_voter_history: Box<Account<'info, VoterHistory>>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Box<Account<'info, TotalVotingPower>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RequestUnstake<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 8 + 8 + 8 + 8 + 1,
+        seeds = [b"unbonding-ticket", user.key().as_ref(), &user_stake.unbonding_count.to_le_bytes()],
+        bump
+    )]
+    pub unbonding_ticket: Account<'info, UnbondingTicket>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_index: u64)]
+pub struct WithdrawUnbonded<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"unbonding-ticket", user.key().as_ref(), &ticket_index.to_le_bytes()],
+        bump = unbonding_ticket.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub unbonding_ticket: Account<'info, UnbondingTicket>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_index: u64)]
+pub struct CancelUnbonding<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"unbonding-ticket", user.key().as_ref(), &ticket_index.to_le_bytes()],
+        bump = unbonding_ticket.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub unbonding_ticket: Account<'info, UnbondingTicket>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateUnbondingPeriod<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeReferralAccount<'info> {
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + ReferralAccount::LEN,
+        seeds = [b"referral-account", referrer.key().as_ref()],
+        bump
+    )]
+    pub referral_account: Account<'info, ReferralAccount>,
+    
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        mut,
+        seeds = [b"referral-account", referrer.key().as_ref()],
+        bump = referral_account.bump,
+        has_one = referrer @ StakingError::InvalidOwner
+    )]
+    pub referral_account: Account<'info, ReferralAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub referrer_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    pub referrer: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUserStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    // Only needed when the stake still owes its referrer referral rewards
+    #[account(
+        seeds = [b"referral-link", user.key().as_ref()],
+        bump = referral_link.bump
+    )]
+    pub referral_link: Option<Box<Account<'info, ReferralLink>>>,
+    
+    #[account(
+        mut,
+        seeds = [b"referral-account", referral_account.referrer.as_ref()],
+        bump = referral_account.bump
+    )]
+    pub referral_account: Option<Box<Account<'info, ReferralAccount>>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SweepDormantStakes<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateProgramState<'info> {
+    /// CHECK: Legacy layout can't be loaded as Account<ProgramState>; owner, discriminator
+    /// and size are checked in read_legacy_account
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump
+    )]
+    pub program_state: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateRewardEscrow<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    /// CHECK: Legacy layout can't be loaded as Account<RewardEscrow>; owner, discriminator
+    /// and size are checked in read_legacy_account
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump
+    )]
+    pub reward_escrow: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    /// CHECK: Legacy layout can't be loaded as Account<UserStake>; owner, discriminator
+    /// and size are checked in read_legacy_account
+    #[account(
+        mut,
+        seeds = [b"user-stake", owner.key().as_ref()],
+        bump
+    )]
+    pub user_stake: UncheckedAccount<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + VoterHistory::LEN,
+        seeds = [b"voter-history", owner.key().as_ref()],
+        bump
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    pub owner: SystemAccount<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAuthority<'info> {
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeCouncil<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + StakingCouncil::LEN,
+        seeds = [b"staking-council", program_state.key().as_ref()],
+        bump
+    )]
+    pub council: Account<'info, StakingCouncil>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeCouncilAction<'info> {
+    #[account(
+        mut,
+        seeds = [b"staking-council", council.program_state.as_ref()],
+        bump = council.bump
+    )]
+    pub council: Account<'info, StakingCouncil>,
+    
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + CouncilProposal::LEN,
+        seeds = [b"council-proposal", council.key().as_ref(), &council.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, CouncilProposal>,
+    
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveCouncilProposal<'info> {
+    #[account(
+        seeds = [b"staking-council", council.program_state.as_ref()],
+        bump = council.bump
+    )]
+    pub council: Account<'info, StakingCouncil>,
+    
+    #[account(
+        mut,
+        seeds = [b"council-proposal", council.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = council
+    )]
+    pub proposal: Account<'info, CouncilProposal>,
+    
+    pub member: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteCouncilProposal<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+        constraint = program_state.authority == council.key() @ StakingError::CouncilNotAuthority,
+    )]
+    pub program_state: Box<Account<'info, ProgramState>>,
+    
+    #[account(
+        mut,
+        seeds = [b"staking-council", program_state.key().as_ref()],
+        bump = council.bump
+    )]
+    pub council: Box<Account<'info, StakingCouncil>>,
+    
+    // Closed on execution; rent goes back to the proposer
+    #[account(
+        mut,
+        seeds = [b"council-proposal", council.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = council,
+        has_one = proposer,
+        close = proposer
+    )]
+    pub proposal: Box<Account<'info, CouncilProposal>>,
+    
+    #[account(
+        mut,
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Box<Account<'info, TierConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"penalty-config", program_state.key().as_ref()],
+        bump = penalty_config.bump
+    )]
+    pub penalty_config: Box<Account<'info, PenaltyConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Box<Account<'info, RewardEscrow>>,
+    
+    #[account(mut)]
+    pub proposer: SystemAccount<'info>,
+    
+    pub executor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCouncilProposal<'info> {
+    #[account(
+        seeds = [b"staking-council", council.program_state.as_ref()],
+        bump = council.bump
+    )]
+    pub council: Account<'info, StakingCouncil>,
+    
+    #[account(
+        mut,
+        seeds = [b"council-proposal", council.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = council,
+        has_one = proposer @ StakingError::InvalidAuthority,
+        close = proposer
+    )]
+    pub proposal: Account<'info, CouncilProposal>,
+    
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PauseProgram<'info> {
+    #[account(
+        mut,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ActivateEmergencyMode<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRewardRate<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetEscrowRunway<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Account<'info, RewardEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeVotingPower<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TotalVotingPower::LEN,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetVotingPowerAt<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"voter-history", voter_history.owner.as_ref()],
+        bump = voter_history.bump
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+}
+
+#[derive(Accounts)]
+pub struct GetUserTier<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    /// CHECK: PDA of `owner`; may not exist yet (tier 0). Deserialized in get_user_tier
+    #[account(
+        seeds = [b"user-stake", owner.key().as_ref()],
+        bump
+    )]
+    pub user_stake: UncheckedAccount<'info>,
+    
+    /// CHECK: Wallet whose tier is looked up; only used for the user-stake seeds
+    pub owner: UncheckedAccount<'info>,
 }
 
-// Context structs
 #[derive(Accounts)]
-pub struct InitializeProgram<'info> {
+pub struct InitializePenaltyConfig<'info> {
     #[account(
-        init,
-        payer = authority,
-        space = 8 + 32 + 32 + 8 + 8 + 1 + 1 + 1 + 1 + 33 + 8,  // Added 1 byte for escrow_vault_bump
         seeds = [b"program-state"],
-        bump
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
     )]
     pub program_state: Account<'info, ProgramState>,
     
     #[account(
         init,
         payer = authority,
-        seeds = [b"stake-vault", program_state.key().as_ref()],
-        bump,
-        token::mint = defai_mint,
-        token::authority = stake_vault,
+        space = 8 + PenaltyConfig::LEN,
+        seeds = [b"penalty-config", program_state.key().as_ref()],
+        bump
     )]
-    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    pub penalty_config: Account<'info, PenaltyConfig>,
     
     #[account(mut)]
     pub authority: Signer<'info>,
-    
-    pub defai_mint: InterfaceAccount<'info, Mint>,
-    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeEscrow<'info> {
+pub struct UpdatePenaltyConfig<'info> {
     #[account(
-        mut,
         seeds = [b"program-state"],
-        bump
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
     )]
     pub program_state: Account<'info, ProgramState>,
     
     #[account(
-        init,
-        payer = authority,
-        space = 8 + 32 + 8 + 8 + 1,
-        seeds = [b"reward-escrow", program_state.key().as_ref()],
-        bump
+        mut,
+        seeds = [b"penalty-config", program_state.key().as_ref()],
+        bump = penalty_config.bump
     )]
-    pub reward_escrow: Account<'info, RewardEscrow>,
+    pub penalty_config: Account<'info, PenaltyConfig>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTierConfig<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
     
     #[account(
         init,
         payer = authority,
-        seeds = [b"escrow-vault", program_state.key().as_ref()],
-        bump,
-        token::mint = defai_mint,
-        token::authority = reward_escrow,
+        space = 8 + TierConfig::LEN,
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump
     )]
-    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub tier_config: Account<'info, TierConfig>,
     
     #[account(mut)]
     pub authority: Signer<'info>,
-    
-    pub defai_mint: InterfaceAccount<'info, Mint>,
-    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct FundEscrow<'info> {
-    // Bring in ProgramState to access authoritative addresses
+pub struct UpdateTierConfig<'info> {
     #[account(
         seeds = [b"program-state"],
-        bump
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
     )]
     pub program_state: Account<'info, ProgramState>,
     
     #[account(
         mut,
-        // Ensure reward_escrow is the correct PDA
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundRewards<'info> {
+    #[account(
+        mut,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.key().as_ref()],
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.key().as_ref()],
+        bump = voter_history.bump,
+        has_one = owner @ StakingError::InvalidOwner
+    )]
+    pub voter_history: Account<'info, VoterHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Account<'info, TotalVotingPower>,
+    
+    #[account(
+        mut,
+        // Add this constraint to ensure it's the official PDA
         seeds = [b"reward-escrow", program_state.key().as_ref()],
-        bump = program_state.reward_escrow_bump
+        bump = program_state.reward_escrow_bump,
     )]
-    pub reward_escrow: Account<'info, RewardEscrow>,
+    pub reward_escrow: Box<Account<'info, RewardEscrow>>,
     
     #[account(
         mut,
-        // Ensure escrow_token_account is the correct ATA and is owned by reward_escrow
         seeds = [b"escrow-vault", program_state.key().as_ref()],
         bump = program_state.escrow_vault_bump,
         token::authority = reward_escrow,
-        token::mint = defai_mint,  // Ensure the ATA's mint matches the provided mint
+        token::mint = defai_mint
     )]
-    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     
-    #[account(mut)]
-    pub funder_token_account: InterfaceAccount<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub funder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     
     #[account(
-        // Ensure the provided mint is the official one from ProgramState
         constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
     )]
-    pub defai_mint: InterfaceAccount<'info, Mint>,
+    pub defai_mint: Box<InterfaceAccount<'info, Mint>>,
+    
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
     
     pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct StakeTokens<'info> {
-    #[account(mut)]
-    pub program_state: Account<'info, ProgramState>,
-    
+pub struct SetAutoCompound<'info> {
     #[account(
-        init_if_needed,
-        payer = user,
-        space = 8 + 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8,  // Added 8 bytes for last_stake_timestamp
+        mut,
         seeds = [b"user-stake", user.key().as_ref()],
-        bump
+        bump,
+        has_one = owner @ StakingError::InvalidOwner,
     )]
     pub user_stake: Account<'info, UserStake>,
     
+    pub user: Signer<'info>,
+    pub owner: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(user: Pubkey)]
+pub struct CrankCompound<'info> {
+    #[account(
+        mut,
+        seeds = [b"program-state"],
+        bump,
+    )]
+    pub program_state: Box<Account<'info, ProgramState>>,
+    
+    #[account(
+        seeds = [b"tier-config", program_state.key().as_ref()],
+        bump = tier_config.bump
+    )]
+    pub tier_config: Box<Account<'info, TierConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"user-stake", user.as_ref()],
+        bump,
+    )]
+    pub user_stake: Box<Account<'info, UserStake>>,
+    
+    #[account(
+        mut,
+        seeds = [b"voter-history", user.as_ref()],
+        bump = voter_history.bump
+    )]
+    pub voter_history: Box<Account<'info, VoterHistory>>,
+    
+    #[account(
+        mut,
+        seeds = [b"total-voting-power", program_state.key().as_ref()],
+        bump = total_voting_power.bump
+    )]
+    pub total_voting_power: Box<Account<'info, TotalVotingPower>>,
+    
+    #[account(
+        mut,
+        seeds = [b"reward-escrow", program_state.key().as_ref()],
+        bump = program_state.reward_escrow_bump,
+    )]
+    pub reward_escrow: Box<Account<'info, RewardEscrow>>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow-vault", program_state.key().as_ref()],
+        bump = program_state.escrow_vault_bump,
+        token::authority = reward_escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    
     #[account(
         mut,
         seeds = [b"stake-vault", program_state.key().as_ref()],
@@ -661,10 +5572,51 @@ pub struct StakeTokens<'info> {
         token::authority = stake_vault,
         token::mint = defai_mint
     )]
-    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     
-    #[account(mut)]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = defai_mint
+    )]
+    pub keeper_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(
+        constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
+    )]
+    pub defai_mint: Box<InterfaceAccount<'info, Mint>>,
+    
+    pub keeper: Signer<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLiquidPool<'info> {
+    #[account(
+        seeds = [b"program-state"],
+        bump,
+        has_one = authority @ StakingError::InvalidAuthority,
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 32 + 8 + 8 + 16 + 8 + 1 + 1,
+        seeds = [b"liquid-pool", program_state.key().as_ref()],
+        bump
+    )]
+    pub liquid_pool: Account<'info, LiquidPool>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"st-mint", program_state.key().as_ref()],
+        bump,
+        mint::decimals = defai_mint.decimals,
+        mint::authority = liquid_pool,
+    )]
+    pub st_mint: InterfaceAccount<'info, Mint>,
     
     #[account(
         constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
@@ -672,24 +5624,41 @@ pub struct StakeTokens<'info> {
     pub defai_mint: InterfaceAccount<'info, Mint>,
     
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub authority: Signer<'info>,
     
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct UnstakeTokens<'info> {
-    #[account(mut)]
-    pub program_state: Account<'info, ProgramState>,
-    
+pub struct StakeLiquid<'info> {
     #[account(
         mut,
-        seeds = [b"user-stake", user.key().as_ref()],
+        seeds = [b"program-state"],
         bump,
-        has_one = owner @ StakingError::InvalidOwner
     )]
-    pub user_stake: Account<'info, UserStake>,
+    pub program_state: Box<Account<'info, ProgramState>>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquid-pool", program_state.key().as_ref()],
+        bump = liquid_pool.bump
+    )]
+    pub liquid_pool: Box<Account<'info, LiquidPool>>,
+    
+    #[account(
+        mut,
+        seeds = [b"st-mint", program_state.key().as_ref()],
+        bump = liquid_pool.st_mint_bump
+    )]
+    pub st_mint: Box<InterfaceAccount<'info, Mint>>,
+    
+    #[account(
+        mut,
+        token::mint = st_mint
+    )]
+    pub user_st_account: Box<InterfaceAccount<'info, TokenAccount>>,
     
     #[account(
         mut,
@@ -698,17 +5667,17 @@ pub struct UnstakeTokens<'info> {
         token::authority = stake_vault,
         token::mint = defai_mint
     )]
-    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     
     #[account(mut)]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     
     #[account(
         mut,
         seeds = [b"reward-escrow", program_state.key().as_ref()],
-        bump = program_state.reward_escrow_bump
+        bump = program_state.reward_escrow_bump,
     )]
-    pub reward_escrow: Account<'info, RewardEscrow>,
+    pub reward_escrow: Box<Account<'info, RewardEscrow>>,
     
     #[account(
         mut,
@@ -717,39 +5686,65 @@ pub struct UnstakeTokens<'info> {
         token::authority = reward_escrow,
         token::mint = defai_mint
     )]
-    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     
     #[account(
         constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
     )]
-    pub defai_mint: InterfaceAccount<'info, Mint>,
+    pub defai_mint: Box<InterfaceAccount<'info, Mint>>,
     
-    #[account(mut)]
     pub user: Signer<'info>,
-    pub owner: SystemAccount<'info>,
     
     pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimRewards<'info> {
-    #[account(mut)]
-    pub program_state: Account<'info, ProgramState>,
-    
+pub struct RedeemLiquid<'info> {
     #[account(
         mut,
-        seeds = [b"user-stake", user.key().as_ref()],
+        seeds = [b"program-state"],
         bump,
-        has_one = owner @ StakingError::InvalidOwner
     )]
-    pub user_stake: Account<'info, UserStake>,
+    pub program_state: Box<Account<'info, ProgramState>>,
+    
+    #[account(
+        mut,
+        seeds = [b"liquid-pool", program_state.key().as_ref()],
+        bump = liquid_pool.bump
+    )]
+    pub liquid_pool: Box<Account<'info, LiquidPool>>,
+    
+    #[account(
+        mut,
+        seeds = [b"st-mint", program_state.key().as_ref()],
+        bump = liquid_pool.st_mint_bump
+    )]
+    pub st_mint: Box<InterfaceAccount<'info, Mint>>,
+    
+    #[account(
+        mut,
+        token::mint = st_mint
+    )]
+    pub user_st_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake-vault", program_state.key().as_ref()],
+        bump = program_state.vault_bump,
+        token::authority = stake_vault,
+        token::mint = defai_mint
+    )]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub user_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     
     #[account(
         mut,
         seeds = [b"reward-escrow", program_state.key().as_ref()],
-        bump = program_state.reward_escrow_bump
+        bump = program_state.reward_escrow_bump,
     )]
-    pub reward_escrow: Account<'info, RewardEscrow>,
+    pub reward_escrow: Box<Account<'info, RewardEscrow>>,
     
     #[account(
         mut,
@@ -758,120 +5753,442 @@ pub struct ClaimRewards<'info> {
         token::authority = reward_escrow,
         token::mint = defai_mint
     )]
-    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     
     #[account(
         constraint = defai_mint.key() == program_state.defai_mint @ StakingError::InvalidMint
     )]
-    pub defai_mint: InterfaceAccount<'info, Mint>,
+    pub defai_mint: Box<InterfaceAccount<'info, Mint>>,
     
     pub user: Signer<'info>,
-    pub owner: SystemAccount<'info>,
     
     pub token_program: Interface<'info, TokenInterface>,
 }
 
-#[derive(Accounts)]
-pub struct UpdateAuthority<'info> {
-    #[account(
-        mut,
-        has_one = authority @ StakingError::InvalidAuthority
-    )]
-    pub program_state: Account<'info, ProgramState>,
-    pub authority: Signer<'info>,
+// Events
+#[event]
+pub struct StakeEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub tier: u8,
+    pub total_staked: u64,
+    pub loyalty_bonus_bps: u16,
 }
 
-#[derive(Accounts)]
-pub struct PauseProgram<'info> {
-    #[account(
-        mut,
-        has_one = authority @ StakingError::InvalidAuthority
-    )]
-    pub program_state: Account<'info, ProgramState>,
-    pub authority: Signer<'info>,
+#[event]
+pub struct StakedForEvent {
+    pub payer: Pubkey,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub tier: u8,
+    pub total_staked: u64,
+    pub locked_until: i64,
 }
 
-#[derive(Accounts)]
-pub struct CompoundRewards<'info> {
-    #[account(mut)]
-    pub program_state: Account<'info, ProgramState>,
-    
-    #[account(
-        mut,
-        seeds = [b"user-stake", user.key().as_ref()],
-        bump,
-        has_one = owner @ StakingError::InvalidOwner
-    )]
-    pub user_stake: Account<'info, UserStake>,
-    
-    #[account(
-        mut,
-        // Add this constraint to ensure it's the official PDA
-        seeds = [b"reward-escrow", program_state.key().as_ref()],
-        bump = program_state.reward_escrow_bump
-    )]
-    pub reward_escrow: Account<'info, RewardEscrow>,
-    
-    pub user: Signer<'info>,
-    pub owner: SystemAccount<'info>,
+#[event]
+pub struct PositionTransferredEvent {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub old_position_index: u64,
+    pub new_position_index: u64,
+    pub amount: u64,
+    pub locked_until: i64,
+}
+
+#[event]
+pub struct StakeTransferredEvent {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+    pub locked_until: i64,
+}
+
+#[event]
+pub struct UnstakeEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub penalty: u64,
+    pub remaining_stake: u64,
+    pub new_tier: u8,
+}
+
+#[event]
+pub struct PenaltyRoutedEvent {
+    pub user: Pubkey,
+    pub penalty: u64,
+    pub destination: PenaltyDestination,
+    pub received: u64,                // Amount that reached the escrow (Escrow / Redistribute)
+}
+
+#[event]
+pub struct PositionOpenedEvent {
+    pub user: Pubkey,
+    pub position_index: u64,
+    pub amount: u64,
+    pub lock_days: u16,
+    pub multiplier_bps: u16,
+    pub locked_until: i64,
+    pub tier: u8,
+}
+
+#[event]
+pub struct PositionClosedEvent {
+    pub user: Pubkey,
+    pub position_index: u64,
+    pub amount: u64,
+    pub penalty: u64,
+    pub remaining_stake: u64,
+    pub new_tier: u8,
+}
+
+#[event]
+pub struct UnbondingRequestedEvent {
+    pub user: Pubkey,
+    pub ticket_index: u64,
+    pub amount: u64,
+    pub unlock_at: i64,
+    pub remaining_stake: u64,
+    pub new_tier: u8,
+}
+
+#[event]
+pub struct UnbondedWithdrawnEvent {
+    pub user: Pubkey,
+    pub ticket_index: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct UnbondingCancelledEvent {
+    pub user: Pubkey,
+    pub ticket_index: u64,
+    pub amount: u64,
+    pub new_stake_amount: u64,
+    pub new_tier: u8,
+}
+
+#[event]
+pub struct UnbondingPeriodUpdatedEvent {
+    pub authority: Pubkey,
+    pub old_period: i64,
+    pub new_period: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidStakeEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub st_amount: u64,
+    pub total_underlying: u64,
+    pub st_supply: u64,
+}
+
+#[event]
+pub struct LiquidRedeemEvent {
+    pub user: Pubkey,
+    pub st_amount: u64,
+    pub amount: u64,
+    pub total_underlying: u64,
+    pub st_supply: u64,
+}
+
+#[event]
+pub struct UserStakeClosedEvent {
+    pub user: Pubkey,
+    pub rewards_paid: u64,
+    pub swept: bool,
+}
+
+#[event]
+pub struct DormantStakesSweptEvent {
+    pub authority: Pubkey,
+    pub closed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EscrowFundingLimitUpdatedEvent {
+    pub authority: Pubkey,
+    pub limit: u64,
+}
+
+#[event]
+pub struct CouncilUpdatedEvent {
+    pub council: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CouncilProposalCreatedEvent {
+    pub council: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: CouncilAction,
+    pub executable_at: i64,
+}
+
+#[event]
+pub struct CouncilProposalApprovedEvent {
+    pub council: Pubkey,
+    pub proposal_id: u64,
+    pub member: Pubkey,
+    pub approvals: u8,
+    pub executable_at: i64,
+}
+
+#[event]
+pub struct CouncilProposalExecutedEvent {
+    pub council: Pubkey,
+    pub proposal_id: u64,
+    pub executor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CouncilProposalCancelledEvent {
+    pub council: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccountMigratedEvent {
+    pub account: Pubkey,
+    pub version: u8,
+    pub payer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsClaimedEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub total_distributed: u64,
+    pub loyalty_bonus_bps: u16,
+}
+
+#[event]
+pub struct ReferralRecordedEvent {
+    pub referrer: Pubkey,
+    pub referee: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimedEvent {
+    pub referrer: Pubkey,
+    pub amount: u64,
+    pub referees: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralSettingsUpdatedEvent {
+    pub authority: Pubkey,
+    pub referral_bps: u16,
+}
+
+#[event]
+pub struct EscrowFundedEvent {
+    pub funder: Pubkey,
+    pub amount: u64,
+    pub new_balance: u64,
+}
+
+#[event]
+pub struct EscrowSafetyBufferUpdatedEvent {
+    pub authority: Pubkey,
+    pub safety_buffer_bps: u16,
+}
+
+#[event]
+pub struct EscrowWithdrawalProposedEvent {
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub executable_at: i64,
+}
+
+#[event]
+pub struct EscrowWithdrawalCancelledEvent {
+    pub authority: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct EscrowWithdrawnEvent {
+    pub authority: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub liabilities: u64,
+    pub safety_buffer: u64,
+    pub balance_before: u64,
+    pub balance_after: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardStreamAddedEvent {
+    pub authority: Pubkey,
+    pub stream_index: u8,
+    pub reward_mint: Pubkey,
+    pub reward_rate: u64,
+    pub end_timestamp: i64,
+}
+
+#[event]
+pub struct RewardStreamUpdatedEvent {
+    pub authority: Pubkey,
+    pub stream_index: u8,
+    pub reward_rate: u64,
+    pub end_timestamp: i64,
+}
+
+#[event]
+pub struct RewardStreamFundedEvent {
+    pub funder: Pubkey,
+    pub stream_index: u8,
+    pub reward_mint: Pubkey,
+    pub amount: u64,
+    pub new_balance: u64,
+}
+
+#[event]
+pub struct StreamRewardsClaimedEvent {
+    pub user: Pubkey,
+    pub stream_index: u8,
+    pub reward_mint: Pubkey,
+    pub amount: u64,
+    pub total_distributed: u64,
+}
+
+#[event]
+pub struct AuthorityUpdatedEvent {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProgramPausedEvent {
+    pub authority: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EmergencyModeProposedEvent {
+    pub authority: Pubkey,
+    pub activates_at: i64,
+}
+
+#[event]
+pub struct EmergencyModeActivatedEvent {
+    pub caller: Pubkey,
+    pub via_timelock: bool,
+    pub paused_at: i64,
+    pub timestamp: i64,
 }
 
-// Events
 #[event]
-pub struct StakeEvent {
+pub struct EmergencyWithdrawEvent {
     pub user: Pubkey,
     pub amount: u64,
-    pub tier: u8,
-    pub total_staked: u64,
+    pub forfeited_rewards: u64,
+    pub positions_closed: u64,
 }
 
 #[event]
-pub struct UnstakeEvent {
+pub struct RewardsCompoundedEvent {
     pub user: Pubkey,
-    pub amount: u64,
-    pub penalty: u64,
-    pub remaining_stake: u64,
+    pub amount_compounded: u64,
+    pub new_stake_amount: u64,
+    pub old_tier: u8,
     pub new_tier: u8,
+    pub timestamp: i64,
 }
 
 #[event]
-pub struct RewardsClaimedEvent {
+pub struct AutoCompoundToggledEvent {
     pub user: Pubkey,
-    pub amount: u64,
-    pub total_distributed: u64,
+    pub enabled: bool,
 }
 
 #[event]
-pub struct EscrowFundedEvent {
-    pub funder: Pubkey,
-    pub amount: u64,
-    pub new_balance: u64,
+pub struct CompoundSettingsUpdatedEvent {
+    pub authority: Pubkey,
+    pub compound_interval: i64,
+    pub compound_tip_bps: u16,
 }
 
 #[event]
-pub struct AuthorityUpdatedEvent {
-    pub old_authority: Pubkey,
-    pub new_authority: Pubkey,
+pub struct AutoCompoundedEvent {
+    pub user: Pubkey,
+    pub keeper: Pubkey,
+    pub amount_compounded: u64,
+    pub tip: u64,
+    pub new_stake_amount: u64,
+    pub new_tier: u8,
     pub timestamp: i64,
 }
 
 #[event]
-pub struct ProgramPausedEvent {
+pub struct RewardRateUpdatedEvent {
     pub authority: Pubkey,
-    pub paused: bool,
+    pub old_rate: u64,
+    pub new_rate: u64,
+    pub acc_reward_per_share: u128,
     pub timestamp: i64,
 }
 
 #[event]
-pub struct RewardsCompoundedEvent {
-    pub user: Pubkey,
-    pub amount_compounded: u64,
-    pub new_stake_amount: u64,
-    pub old_tier: u8,
-    pub new_tier: u8,
+pub struct EmissionScheduleUpdatedEvent {
+    pub authority: Pubkey,
+    pub epoch: u64,
+    pub epoch_duration: i64,
+    pub epoch_reward_budget: u64,
+    pub reward_rate: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NextEpochBudgetSetEvent {
+    pub authority: Pubkey,
+    pub current_epoch: u64,
+    pub next_epoch_reward_budget: u64,
+    pub next_epoch_start: i64,
+}
+
+#[event]
+pub struct LoyaltyConfigUpdatedEvent {
+    pub authority: Pubkey,
+    pub steps: Vec<LoyaltyStep>,
+    pub cap_bps: u16,
+}
+
+#[event]
+pub struct PenaltyConfigUpdatedEvent {
+    pub authority: Pubkey,
+    pub steps: Vec<PenaltyStep>,
+    pub destination: PenaltyDestination,
+    pub treasury: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TierConfigProposedEvent {
+    pub authority: Pubkey,
+    pub tiers: Vec<TierInfo>,
+    pub executable_at: i64,
+}
+
+#[event]
+pub struct TierConfigUpdatedEvent {
+    pub authority: Pubkey,
+    pub tiers: Vec<TierInfo>,
     pub timestamp: i64,
 }
 
@@ -900,65 +6217,918 @@ pub enum StakingError {
     TimelockNotExpired,
     #[msg("Invalid mint address")]
     InvalidMint,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Invalid tier configuration")]
+    InvalidTierConfig,
+    #[msg("No pending tier config change")]
+    NoPendingTierChange,
+    #[msg("Unsupported lock duration")]
+    InvalidLockDuration,
+    #[msg("Unbonding cooldown has not finished")]
+    UnbondingNotComplete,
+    #[msg("Unbonding period must be between 1 and 30 days")]
+    InvalidUnbondingPeriod,
+    #[msg("Invalid reward stream")]
+    InvalidRewardStream,
+    #[msg("All reward stream slots are in use")]
+    RewardStreamsFull,
+    #[msg("Reward rate is set by the emission schedule")]
+    EmissionScheduleActive,
+    #[msg("Invalid emission schedule")]
+    InvalidEmissionSchedule,
+    #[msg("Emergency mode is not active")]
+    EmergencyModeNotActive,
+    #[msg("Emergency mode is already active")]
+    EmergencyModeActive,
+    #[msg("Emergency mode timelock or pause threshold not reached")]
+    EmergencyConditionsNotMet,
+    #[msg("Open stake positions don't match the stake")]
+    InvalidPositionAccounts,
+    #[msg("Checkpoint slot must be in the past")]
+    InvalidCheckpointSlot,
+    #[msg("Slot is older than the oldest stored checkpoint")]
+    CheckpointTooOld,
+    #[msg("Invalid penalty curve")]
+    InvalidPenaltyConfig,
+    #[msg("Invalid or missing treasury token account")]
+    InvalidTreasury,
+    #[msg("Invalid auto-compound settings")]
+    InvalidCompoundSettings,
+    #[msg("Invalid crank accounts")]
+    InvalidCrankAccounts,
+    #[msg("Stake still holds tokens or rewards")]
+    StakeNotEmpty,
+    #[msg("Withdraw or cancel open unbonding tickets first")]
+    OpenUnbondingTickets,
+    #[msg("Claim stream rewards first")]
+    UnclaimedStreamRewards,
+    #[msg("Stake is not dormant yet")]
+    StakeNotDormant,
+    #[msg("Invalid sweep accounts")]
+    InvalidSweepAccounts,
+    #[msg("Account uses an outdated layout; run the matching migrate instruction")]
+    AccountNotMigrated,
+    #[msg("Account is already on the current layout")]
+    AlreadyMigrated,
+    #[msg("Account data doesn't match a known layout")]
+    InvalidAccountLayout,
+    #[msg("Invalid council members or threshold")]
+    InvalidCouncilConfig,
+    #[msg("Signer is not a council member")]
+    NotCouncilMember,
+    #[msg("Member has already approved this proposal")]
+    AlreadyApproved,
+    #[msg("Proposal has not reached the approval threshold")]
+    ProposalNotApproved,
+    #[msg("Council does not hold the program authority")]
+    CouncilNotAuthority,
+    #[msg("Deposit exceeds the escrow funding limit")]
+    EscrowFundingLimitExceeded,
+    #[msg("Invalid escrow withdrawal")]
+    InvalidEscrowWithdrawal,
+    #[msg("No pending escrow withdrawal")]
+    NoPendingEscrowWithdrawal,
+    #[msg("Withdrawal would leave the escrow below liabilities plus the safety buffer")]
+    EscrowWithdrawalExceedsSurplus,
+    #[msg("Invalid referrer or referral accounts")]
+    InvalidReferral,
+    #[msg("Invalid referral claim accounts")]
+    InvalidReferralAccounts,
+    #[msg("Invalid referral settings")]
+    InvalidReferralSettings,
+    #[msg("Tier lookup used foreign accounts or returned data for another program or wallet")]
+    InvalidTierResponse,
+    #[msg("Invalid loyalty boost steps or cap")]
+    InvalidLoyaltyConfig,
 }
 
 // Helper functions
-fn get_tier(amount: u64) -> Result<u8> {
-    if amount >= INFINITE_MIN {
-        Ok(3) // Infinite
-    } else if amount >= TITANIUM_MIN {
-        Ok(2) // Titanium
-    } else if amount >= GOLD_MIN {
-        Ok(1) // Gold
-    } else {
-        Ok(0) // No tier
+// Returns the tier id (index into the tier table) for `amount`. Validated tables cover every
+// amount, so the fallback to tier 0 only matters for an empty table.
+fn get_tier(tier_config: &TierConfig, amount: u64) -> u8 {
+    tier_config.tiers
+        .iter()
+        .rposition(|tier| amount >= tier.min_amount)
+        .map(|index| index as u8)
+        .unwrap_or(0)
+}
+
+fn get_tier_multiplier(tier_config: &TierConfig, tier: u8) -> u16 {
+    tier_config.tiers
+        .get(tier as usize)
+        .map(|tier| tier.multiplier_bps)
+        .unwrap_or(0)
+}
+
+// Highest loyalty step reached by continuous staking since `stake_timestamp`, capped
+fn get_loyalty_bonus(program_state: &ProgramState, stake_timestamp: i64, now: i64) -> u16 {
+    let days_staked = (now - stake_timestamp).max(0) / 86400;
+    program_state.loyalty_steps
+        .iter()
+        .filter(|step| step.days > 0 && days_staked >= step.days as i64)
+        .map(|step| step.bonus_bps)
+        .max()
+        .unwrap_or(0)
+        .min(program_state.loyalty_cap_bps)
+}
+
+// Partial unstakes move the loyalty clock forward in proportion to the share withdrawn;
+// withdrawing everything restarts it
+fn reset_loyalty(user_stake: &mut UserStake, amount: u64, now: i64) -> Result<()> {
+    if user_stake.staked_amount == 0 {
+        return Ok(());
     }
+    let elapsed = (now - user_stake.stake_timestamp).max(0) as u128;
+    let reset = elapsed
+        .checked_mul(amount as u128)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(user_stake.staked_amount as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    user_stake.stake_timestamp = user_stake.stake_timestamp
+        .checked_add(reset as i64)
+        .ok_or(StakingError::MathOverflow)?;
+    
+    Ok(())
 }
 
-fn get_tier_apy(amount: u64) -> Result<u16> {
-    if amount >= INFINITE_MIN {
-        Ok(INFINITE_APY_BPS)
-    } else if amount >= TITANIUM_MIN {
-        Ok(TITANIUM_APY_BPS)
-    } else if amount >= GOLD_MIN {
-        Ok(GOLD_APY_BPS)
-    } else {
-        Err(StakingError::AmountTooLow.into())
+fn validate_loyalty_steps(steps: &[LoyaltyStep], cap_bps: u16) -> Result<()> {
+    require!(steps.len() <= MAX_LOYALTY_STEPS, StakingError::InvalidLoyaltyConfig);
+    require!(cap_bps <= MAX_LOYALTY_BONUS_BPS, StakingError::InvalidLoyaltyConfig);
+    
+    for (i, step) in steps.iter().enumerate() {
+        require!(step.days > 0, StakingError::InvalidLoyaltyConfig);
+        require!(step.bonus_bps <= cap_bps, StakingError::InvalidLoyaltyConfig);
+        if i > 0 {
+            require!(step.days > steps[i - 1].days, StakingError::InvalidLoyaltyConfig);
+            require!(step.bonus_bps >= steps[i - 1].bonus_bps, StakingError::InvalidLoyaltyConfig);
+        }
     }
+    
+    Ok(())
 }
 
-fn calculate_rewards(
-    staked_amount: u64,
-    tier_apy_bps: u16,
-    last_claim_timestamp: i64,
-    current_timestamp: i64,
+fn get_lock_multiplier(lock_days: u16) -> Option<u16> {
+    LOCK_OPTIONS
+        .iter()
+        .find(|(days, _)| *days == lock_days)
+        .map(|(_, multiplier_bps)| *multiplier_bps)
+}
+
+// Extra share weight a position earns on top of its principal
+fn get_lock_bonus(amount: u64, multiplier_bps: u16) -> Result<u64> {
+    let bonus = (amount as u128)
+        .checked_mul(multiplier_bps.saturating_sub(BASIS_POINTS as u16) as u128)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(BASIS_POINTS as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    u64::try_from(bonus).map_err(|_| StakingError::MathOverflow.into())
+}
+
+// Tiers must be contiguous and non-overlapping from 0 up to an unbounded last tier. Tier 0 is
+// the unstaked band below the first staking tier and earns nothing.
+fn validate_tiers(tiers: &[TierInfo]) -> Result<()> {
+    require!(
+        tiers.len() >= 2 && tiers.len() <= MAX_TIERS,
+        StakingError::InvalidTierConfig
+    );
+    require!(
+        tiers[0].min_amount == 0 && tiers[0].multiplier_bps == 0,
+        StakingError::InvalidTierConfig
+    );
+    require!(tiers[tiers.len() - 1].max_amount == u64::MAX, StakingError::InvalidTierConfig);
+    
+    for (i, tier) in tiers.iter().enumerate() {
+        require!(tier.name.len() <= MAX_TIER_NAME_LEN, StakingError::InvalidTierConfig);
+        require!(tier.min_amount <= tier.max_amount, StakingError::InvalidTierConfig);
+        if i > 0 {
+            require!(
+                tiers[i - 1].max_amount.checked_add(1) == Some(tier.min_amount),
+                StakingError::InvalidTierConfig
+            );
+        }
+    }
+    
+    Ok(())
+}
+
+// Advance acc_reward_per_share to `now`, splitting reward_rate tokens/sec across all shares.
+// Rolls emission epochs and advances every reward stream. Must run before any change to
+// shares or emission rates.
+fn update_reward_index(program_state: &mut ProgramState, now: i64) -> Result<()> {
+    if program_state.epoch_duration > 0 {
+        let epoch_end = program_state.epoch_start_timestamp
+            .checked_add(program_state.epoch_duration)
+            .ok_or(StakingError::MathOverflow)?;
+        if now >= epoch_end {
+            // Close out the current epoch at its own rate
+            accrue_reward_index(program_state, epoch_end)?;
+            
+            // Every later epoch runs on next_epoch_reward_budget, so skipped epochs accrue in one step
+            let epochs_elapsed = (now - epoch_end) / program_state.epoch_duration + 1;
+            program_state.current_epoch = program_state.current_epoch
+                .checked_add(epochs_elapsed as u64)
+                .ok_or(StakingError::MathOverflow)?;
+            program_state.epoch_start_timestamp = epoch_end
+                .checked_add((epochs_elapsed - 1) * program_state.epoch_duration)
+                .ok_or(StakingError::MathOverflow)?;
+            program_state.epoch_reward_budget = program_state.next_epoch_reward_budget;
+            program_state.reward_rate = program_state.epoch_reward_budget / program_state.epoch_duration as u64;
+        }
+    }
+    accrue_reward_index(program_state, now)?;
+    
+    // The liquid pool only compounds DEFAI, so streams are split across user shares only
+    let stream_shares = program_state.total_reward_shares
+        .checked_sub(program_state.liquid_reward_shares)
+        .ok_or(StakingError::MathOverflow)?;
+    for stream in program_state.reward_streams.iter_mut() {
+        update_stream_index(stream, stream_shares, now)?;
+    }
+    
+    Ok(())
+}
+
+// Emit reward_rate tokens/sec from last_reward_timestamp up to `until`
+fn accrue_reward_index(program_state: &mut ProgramState, until: i64) -> Result<()> {
+    if until <= program_state.last_reward_timestamp {
+        return Ok(());
+    }
+    
+    if program_state.total_reward_shares > 0 && program_state.reward_rate > 0 {
+        let elapsed = (until - program_state.last_reward_timestamp) as u64;
+        let emitted = program_state.reward_rate
+            .checked_mul(elapsed)
+            .ok_or(StakingError::MathOverflow)?;
+        
+        let index_delta = (emitted as u128)
+            .checked_mul(ACC_REWARD_PRECISION)
+            .ok_or(StakingError::MathOverflow)?
+            .checked_div(program_state.total_reward_shares as u128)
+            .ok_or(StakingError::MathOverflow)?;
+        
+        program_state.acc_reward_per_share = program_state.acc_reward_per_share
+            .checked_add(index_delta)
+            .ok_or(StakingError::MathOverflow)?;
+        program_state.total_pending_rewards = program_state.total_pending_rewards
+            .checked_add(emitted)
+            .ok_or(StakingError::MathOverflow)?;
+    }
+    
+    program_state.last_reward_timestamp = until;
+    Ok(())
+}
+
+// When the unreserved escrow balance runs out at the current rate, then at the
+// next epochs' rate. Assumes there are stakers to emit to.
+fn project_escrow_exhaustion(program_state: &ProgramState, available: u64, now: i64) -> i64 {
+    let mut remaining = available;
+    let mut from = now;
+    let mut rate = program_state.reward_rate;
+    
+    if program_state.epoch_duration > 0 {
+        let epoch_end = program_state.epoch_start_timestamp.saturating_add(program_state.epoch_duration);
+        let epoch_emission = rate.saturating_mul(epoch_end.saturating_sub(now).max(0) as u64);
+        if rate > 0 && remaining < epoch_emission {
+            return now.saturating_add((remaining / rate) as i64);
+        }
+        remaining -= epoch_emission;
+        from = epoch_end;
+        rate = program_state.next_epoch_reward_budget / program_state.epoch_duration as u64;
+    }
+    
+    if rate == 0 {
+        return i64::MAX;
+    }
+    from.saturating_add(i64::try_from(remaining / rate).unwrap_or(i64::MAX))
+}
+
+fn update_stream_index(stream: &mut RewardStream, total_shares: u64, now: i64) -> Result<()> {
+    if stream.reward_mint == Pubkey::default() {
+        return Ok(());
+    }
+    
+    let accrue_until = now.min(stream.end_timestamp);
+    if accrue_until <= stream.last_reward_timestamp {
+        return Ok(());
+    }
+    
+    if total_shares > 0 && stream.reward_rate > 0 {
+        let elapsed = (accrue_until - stream.last_reward_timestamp) as u64;
+        let emitted = stream.reward_rate
+            .checked_mul(elapsed)
+            .ok_or(StakingError::MathOverflow)?;
+        
+        let index_delta = (emitted as u128)
+            .checked_mul(ACC_REWARD_PRECISION)
+            .ok_or(StakingError::MathOverflow)?
+            .checked_div(total_shares as u128)
+            .ok_or(StakingError::MathOverflow)?;
+        
+        stream.acc_reward_per_share = stream.acc_reward_per_share
+            .checked_add(index_delta)
+            .ok_or(StakingError::MathOverflow)?;
+        stream.total_pending_rewards = stream.total_pending_rewards
+            .checked_add(emitted)
+            .ok_or(StakingError::MathOverflow)?;
+    }
+    
+    stream.last_reward_timestamp = accrue_until;
+    Ok(())
+}
+
+fn get_reward_stream_mut(program_state: &mut ProgramState, stream_index: u8) -> Result<&mut RewardStream> {
+    program_state.reward_streams
+        .get_mut(stream_index as usize)
+        .filter(|stream| stream.reward_mint != Pubkey::default())
+        .ok_or(StakingError::InvalidRewardStream.into())
+}
+
+fn accumulated_rewards(reward_shares: u64, acc_reward_per_share: u128) -> Result<u128> {
+    (reward_shares as u128)
+        .checked_mul(acc_reward_per_share)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(ACC_REWARD_PRECISION)
+        .ok_or(StakingError::MathOverflow.into())
+}
+
+// Move rewards accrued since the last settlement into rewards_earned. Returns the amount settled.
+// For referred stakes, referral_bps of it is owed to the referrer on top and counted as a liability.
+fn settle_rewards(program_state: &mut ProgramState, user_stake: &mut UserStake) -> Result<u64> {
+    let accumulated = accumulated_rewards(user_stake.reward_shares, program_state.acc_reward_per_share)?;
+    let pending = u64::try_from(accumulated.saturating_sub(user_stake.reward_debt))
+        .map_err(|_| StakingError::MathOverflow)?;
+    
+    user_stake.rewards_earned = user_stake.rewards_earned
+        .checked_add(pending)
+        .ok_or(StakingError::MathOverflow)?;
+    user_stake.reward_debt = accumulated;
+    
+    if user_stake.referred && pending > 0 {
+        let referral_reward = (pending as u128)
+            .checked_mul(program_state.referral_bps as u128)
+            .ok_or(StakingError::MathOverflow)?
+            .checked_div(BASIS_POINTS as u128)
+            .ok_or(StakingError::MathOverflow)? as u64;
+        user_stake.referral_rewards_owed = user_stake.referral_rewards_owed
+            .checked_add(referral_reward)
+            .ok_or(StakingError::MathOverflow)?;
+        program_state.total_referral_liability = program_state.total_referral_liability
+            .checked_add(referral_reward)
+            .ok_or(StakingError::MathOverflow)?;
+    }
+    
+    for (slot, stream) in program_state.reward_streams.iter().enumerate() {
+        if stream.reward_mint == Pubkey::default() {
+            continue;
+        }
+        let stream_accumulated = accumulated_rewards(user_stake.reward_shares, stream.acc_reward_per_share)?;
+        let stream_pending = u64::try_from(stream_accumulated.saturating_sub(user_stake.stream_reward_debts[slot]))
+            .map_err(|_| StakingError::MathOverflow)?;
+        user_stake.stream_rewards_owed[slot] = user_stake.stream_rewards_owed[slot]
+            .checked_add(stream_pending)
+            .ok_or(StakingError::MathOverflow)?;
+        user_stake.stream_reward_debts[slot] = stream_accumulated;
+    }
+    
+    Ok(pending)
+}
+
+// Recompute a user's tier and shares from staked_amount against the current tier table.
+// Call after settle_rewards.
+fn update_reward_shares(
+    program_state: &mut ProgramState,
+    tier_config: &TierConfig,
+    user_stake: &mut UserStake,
+    now: i64,
+) -> Result<()> {
+    user_stake.tier = get_tier(tier_config, user_stake.staked_amount);
+    user_stake.loyalty_bonus_bps = get_loyalty_bonus(program_state, user_stake.stake_timestamp, now);
+    
+    // Position lock bonuses add weight but never count towards the tier threshold
+    let weighted_amount = user_stake.staked_amount
+        .checked_add(user_stake.position_bonus_amount)
+        .ok_or(StakingError::MathOverflow)?;
+    let new_shares = (weighted_amount as u128)
+        .checked_mul(get_tier_multiplier(tier_config, user_stake.tier) as u128)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_mul(BASIS_POINTS as u128 + user_stake.loyalty_bonus_bps as u128)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(BASIS_POINTS as u128 * BASIS_POINTS as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    let new_shares = u64::try_from(new_shares).map_err(|_| StakingError::MathOverflow)?;
+    
+    program_state.total_reward_shares = program_state.total_reward_shares
+        .checked_sub(user_stake.reward_shares)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_add(new_shares)
+        .ok_or(StakingError::MathOverflow)?;
+    user_stake.reward_shares = new_shares;
+    user_stake.reward_debt = accumulated_rewards(new_shares, program_state.acc_reward_per_share)?;
+    for (slot, stream) in program_state.reward_streams.iter().enumerate() {
+        user_stake.stream_reward_debts[slot] = accumulated_rewards(new_shares, stream.acc_reward_per_share)?;
+    }
+    
+    Ok(())
+}
+
+// Token balance gained by `vault` since `balance_before`. Transfer-fee (Token-2022) mints
+// deliver less than the amount sent, so inbound transfers are credited with this instead.
+fn received_amount(vault: &mut InterfaceAccount<TokenAccount>, balance_before: u64) -> Result<u64> {
+    vault.reload()?;
+    vault.amount
+        .checked_sub(balance_before)
+        .ok_or(StakingError::MathOverflow.into())
+}
+
+// Record a wallet's new voting power and move the global total by the difference
+fn checkpoint_voting_power(
+    voter_history: &mut VoterHistory,
+    total_voting_power: &mut TotalVotingPower,
+    voting_power: u64,
+    slot: u64,
+) -> Result<()> {
+    let previous = voter_history.history.latest();
+    let new_total = total_voting_power.history.latest()
+        .checked_sub(previous)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_add(voting_power)
+        .ok_or(StakingError::MathOverflow)?;
+    
+    voter_history.history.push(slot, voting_power, MAX_VOTER_CHECKPOINTS);
+    total_voting_power.history.push(slot, new_total, MAX_TOTAL_CHECKPOINTS);
+    
+    Ok(())
+}
+
+// Same settlement as settle_rewards, but for the liquid pool. Returns how much can be
+// harvested from escrow right now; any shortfall is carried in unharvested_rewards.
+fn settle_liquid_pool(
+    program_state: &ProgramState,
+    pool: &mut LiquidPool,
+    escrow_balance: u64,
 ) -> Result<u64> {
-    let time_elapsed = (current_timestamp - last_claim_timestamp) as u64;
+    let accumulated = accumulated_rewards(pool.reward_shares, program_state.acc_reward_per_share)?;
+    let pending = u64::try_from(accumulated.saturating_sub(pool.reward_debt))
+        .map_err(|_| StakingError::MathOverflow)?;
+    pool.reward_debt = accumulated;
     
-    // Calculate rewards: amount * apy * time / (year * basis_points)
-    let rewards = (staked_amount as u128)
-        .checked_mul(tier_apy_bps as u128).unwrap()
-        .checked_mul(time_elapsed as u128).unwrap()
-        .checked_div(SECONDS_PER_YEAR as u128).unwrap()
-        .checked_div(BASIS_POINTS as u128).unwrap() as u64;
+    let owed = pool.unharvested_rewards
+        .checked_add(pending)
+        .ok_or(StakingError::MathOverflow)?;
+    let harvested = owed.min(escrow_balance);
+    pool.unharvested_rewards = owed - harvested;
+    
+    Ok(harvested)
+}
+
+// The pool holds shares 1:1 with its underlying; tier multipliers don't apply
+fn update_liquid_pool_shares(program_state: &mut ProgramState, pool: &mut LiquidPool) -> Result<()> {
+    program_state.total_reward_shares = program_state.total_reward_shares
+        .checked_sub(pool.reward_shares)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_add(pool.total_underlying)
+        .ok_or(StakingError::MathOverflow)?;
+    program_state.liquid_reward_shares = pool.total_underlying;
+    pool.reward_shares = pool.total_underlying;
+    pool.reward_debt = accumulated_rewards(pool.reward_shares, program_state.acc_reward_per_share)?;
     
-    Ok(rewards)
+    Ok(())
+}
+
+// stDEFAI to mint for `amount` DEFAI; 1:1 while the pool is empty
+fn liquid_to_mint(amount: u64, total_underlying: u64, st_supply: u64) -> Result<u64> {
+    if st_supply == 0 || total_underlying == 0 {
+        return Ok(amount);
+    }
+    let st_amount = (amount as u128)
+        .checked_mul(st_supply as u128)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(total_underlying as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    u64::try_from(st_amount).map_err(|_| StakingError::MathOverflow.into())
+}
+
+// DEFAI returned for burning `st_amount` stDEFAI
+fn liquid_to_redeem(st_amount: u64, total_underlying: u64, st_supply: u64) -> Result<u64> {
+    require!(st_supply > 0 && st_amount <= st_supply, StakingError::InsufficientStake);
+    let amount = (st_amount as u128)
+        .checked_mul(total_underlying as u128)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(st_supply as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    u64::try_from(amount).map_err(|_| StakingError::MathOverflow.into())
 }
 
 fn calculate_unstake_penalty(
+    penalty_config: &PenaltyConfig,
     stake_timestamp: i64,
     current_timestamp: i64,
     amount: u64,
 ) -> Result<u64> {
     let days_staked = (current_timestamp - stake_timestamp) / 86400;
     
-    let penalty_bps = if days_staked < 30 {
-        200  // 2%
-    } else if days_staked < 90 {
-        100  // 1%
-    } else {
-        0    // No penalty
-    };
+    let penalty_bps = penalty_config.steps
+        .iter()
+        .find(|step| days_staked < step.days as i64)
+        .map(|step| step.bps)
+        .unwrap_or(0);  // Past the last step: no penalty
     
     Ok((amount as u128 * penalty_bps as u128 / BASIS_POINTS as u128) as u64)
 }
+
+// Steps must be strictly ascending in days with non-increasing, capped penalties
+fn validate_penalty_steps(steps: &[PenaltyStep]) -> Result<()> {
+    require!(steps.len() <= MAX_PENALTY_STEPS, StakingError::InvalidPenaltyConfig);
+    
+    for (i, step) in steps.iter().enumerate() {
+        require!(step.days > 0, StakingError::InvalidPenaltyConfig);
+        require!(step.bps <= MAX_PENALTY_BPS, StakingError::InvalidPenaltyConfig);
+        if i > 0 {
+            require!(step.days > steps[i - 1].days, StakingError::InvalidPenaltyConfig);
+            require!(step.bps <= steps[i - 1].bps, StakingError::InvalidPenaltyConfig);
+        }
+    }
+    
+    Ok(())
+}
+
+// Track how long the program has been paused for the emergency mode fallback
+fn set_paused(program_state: &mut ProgramState, paused: bool, now: i64) {
+    if paused && !program_state.paused {
+        program_state.paused_at = now;
+    } else if !paused {
+        program_state.paused_at = 0;
+    }
+    program_state.paused = paused;
+}
+
+fn validate_council(members: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(
+        !members.is_empty() && members.len() <= MAX_COUNCIL_MEMBERS,
+        StakingError::InvalidCouncilConfig
+    );
+    require!(
+        threshold > 0 && threshold as usize <= members.len(),
+        StakingError::InvalidCouncilConfig
+    );
+    for (i, member) in members.iter().enumerate() {
+        require!(!members[..i].contains(member), StakingError::InvalidCouncilConfig);
+    }
+    
+    Ok(())
+}
+
+fn validate_council_action(action: &CouncilAction) -> Result<()> {
+    match action {
+        CouncilAction::UpdateTiers { tiers } => validate_tiers(tiers),
+        CouncilAction::RotateAuthority { new_authority } => {
+            require_keys_neq!(*new_authority, Pubkey::default(), StakingError::InvalidAuthority);
+            Ok(())
+        }
+        CouncilAction::UpdateCouncil { members, threshold } => validate_council(members, *threshold),
+        CouncilAction::UpdateLoyaltyConfig { steps, cap_bps } => validate_loyalty_steps(steps, *cap_bps),
+        CouncilAction::UpdatePenaltyConfig { steps, .. } => validate_penalty_steps(steps),
+        // Checked against the current state when the proposal executes
+        _ => Ok(()),
+    }
+}
+
+// Setters shared by the authority instructions and the matching council actions.
+// Callers emit the update event with whichever authority signed off.
+
+fn apply_reward_rate(program_state: &mut ProgramState, reward_rate: u64, now: i64) -> Result<u64> {
+    // With a schedule the rate is derived from the epoch budget
+    require!(program_state.epoch_duration == 0, StakingError::EmissionScheduleActive);
+    
+    // Accrue everything owed at the old rate before switching
+    update_reward_index(program_state, now)?;
+    
+    let old_rate = program_state.reward_rate;
+    program_state.reward_rate = reward_rate;
+    Ok(old_rate)
+}
+
+fn apply_emission_schedule(
+    program_state: &mut ProgramState,
+    epoch_duration: i64,
+    epoch_reward_budget: u64,
+    now: i64,
+) -> Result<()> {
+    require!(epoch_duration >= 0, StakingError::InvalidEmissionSchedule);
+    
+    // Accrue everything owed under the old schedule before switching
+    update_reward_index(program_state, now)?;
+    
+    program_state.epoch_duration = epoch_duration;
+    if epoch_duration > 0 {
+        program_state.current_epoch = program_state.current_epoch
+            .checked_add(1)
+            .ok_or(StakingError::MathOverflow)?;
+        program_state.epoch_start_timestamp = now;
+        program_state.epoch_reward_budget = epoch_reward_budget;
+        program_state.next_epoch_reward_budget = epoch_reward_budget;
+        program_state.reward_rate = epoch_reward_budget / epoch_duration as u64;
+    } else {
+        program_state.epoch_reward_budget = 0;
+        program_state.next_epoch_reward_budget = 0;
+        program_state.reward_rate = 0;
+    }
+    Ok(())
+}
+
+fn apply_next_epoch_budget(program_state: &mut ProgramState, epoch_reward_budget: u64, now: i64) -> Result<()> {
+    require!(program_state.epoch_duration > 0, StakingError::InvalidEmissionSchedule);
+    
+    // Roll over first so the new budget doesn't land on an epoch that already ended
+    update_reward_index(program_state, now)?;
+    program_state.next_epoch_reward_budget = epoch_reward_budget;
+    Ok(())
+}
+
+fn apply_reward_stream_update(
+    program_state: &mut ProgramState,
+    stream_index: u8,
+    reward_rate: u64,
+    end_timestamp: i64,
+    now: i64,
+) -> Result<()> {
+    require!(end_timestamp > now, StakingError::InvalidRewardStream);
+    update_reward_index(program_state, now)?;
+    
+    let stream = get_reward_stream_mut(program_state, stream_index)?;
+    stream.reward_rate = reward_rate;
+    stream.end_timestamp = end_timestamp;
+    // An ended stream stopped accruing at its old end; don't emit retroactively for the gap
+    stream.last_reward_timestamp = now;
+    Ok(())
+}
+
+// Returns the previous period
+fn apply_unbonding_period(program_state: &mut ProgramState, unbonding_period: i64) -> Result<i64> {
+    require!(
+        (MIN_UNBONDING_PERIOD..=MAX_UNBONDING_PERIOD).contains(&unbonding_period),
+        StakingError::InvalidUnbondingPeriod
+    );
+    Ok(std::mem::replace(&mut program_state.unbonding_period, unbonding_period))
+}
+
+fn apply_referral_bps(program_state: &mut ProgramState, referral_bps: u16) -> Result<()> {
+    require!(referral_bps <= MAX_REFERRAL_BPS, StakingError::InvalidReferralSettings);
+    program_state.referral_bps = referral_bps;
+    Ok(())
+}
+
+fn apply_compound_settings(
+    program_state: &mut ProgramState,
+    compound_interval: i64,
+    compound_tip_bps: u16,
+) -> Result<()> {
+    require!(compound_interval >= 0, StakingError::InvalidCompoundSettings);
+    require!(compound_tip_bps <= MAX_COMPOUND_TIP_BPS, StakingError::InvalidCompoundSettings);
+    program_state.compound_interval = compound_interval;
+    program_state.compound_tip_bps = compound_tip_bps;
+    Ok(())
+}
+
+fn apply_loyalty_config(program_state: &mut ProgramState, steps: &[LoyaltyStep], cap_bps: u16) -> Result<()> {
+    validate_loyalty_steps(steps, cap_bps)?;
+    program_state.loyalty_steps = [LoyaltyStep::default(); MAX_LOYALTY_STEPS];
+    program_state.loyalty_steps[..steps.len()].copy_from_slice(steps);
+    program_state.loyalty_cap_bps = cap_bps;
+    Ok(())
+}
+
+fn apply_penalty_config(
+    penalty_config: &mut PenaltyConfig,
+    steps: Vec<PenaltyStep>,
+    destination: PenaltyDestination,
+    treasury: Pubkey,
+) -> Result<()> {
+    validate_penalty_steps(&steps)?;
+    penalty_config.steps = steps;
+    penalty_config.destination = destination;
+    penalty_config.treasury = treasury;
+    Ok(())
+}
+
+fn apply_emergency_mode_proposal(program_state: &mut ProgramState, now: i64) -> Result<()> {
+    require!(!program_state.emergency_mode, StakingError::EmergencyModeActive);
+    program_state.emergency_mode_timestamp = now + ADMIN_TIMELOCK_DURATION;
+    Ok(())
+}
+
+// Approvals from current council members only
+fn count_approvals(council: &StakingCouncil, proposal: &CouncilProposal) -> usize {
+    proposal.approvals
+        .iter()
+        .filter(|approver| council.members.contains(approver))
+        .count()
+}
+
+// Move an unstake penalty out of the stake vault. Returns the amount that landed in the
+// escrow vault (Escrow / Redistribute); burned or treasury penalties return 0.
+#[allow(clippy::too_many_arguments)]
+fn route_penalty<'info>(
+    destination: PenaltyDestination,
+    penalty: u64,
+    token_program: &Interface<'info, TokenInterface>,
+    stake_vault: &InterfaceAccount<'info, TokenAccount>,
+    escrow_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    treasury_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    defai_mint: &InterfaceAccount<'info, Mint>,
+    signer: &[&[&[u8]]],
+) -> Result<u64> {
+    if penalty == 0 {
+        return Ok(0);
+    }
+    
+    match destination {
+        PenaltyDestination::Escrow | PenaltyDestination::Redistribute => {
+            let escrow_balance_before = escrow_token_account.amount;
+            let transfer_penalty_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: stake_vault.to_account_info(),
+                    to: escrow_token_account.to_account_info(),
+                    authority: stake_vault.to_account_info(),
+                    mint: defai_mint.to_account_info(),
+                },
+                signer,
+            );
+            transfer_checked(transfer_penalty_ctx, penalty, defai_mint.decimals)?;
+            received_amount(escrow_token_account, escrow_balance_before)
+        }
+        PenaltyDestination::Burn => {
+            let burn_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                Burn {
+                    mint: defai_mint.to_account_info(),
+                    from: stake_vault.to_account_info(),
+                    authority: stake_vault.to_account_info(),
+                },
+                signer,
+            );
+            burn(burn_ctx, penalty)?;
+            Ok(0)
+        }
+        PenaltyDestination::Treasury => {
+            let treasury_token_account = treasury_token_account.ok_or(StakingError::InvalidTreasury)?;
+            let transfer_penalty_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: stake_vault.to_account_info(),
+                    to: treasury_token_account.to_account_info(),
+                    authority: stake_vault.to_account_info(),
+                    mint: defai_mint.to_account_info(),
+                },
+                signer,
+            );
+            transfer_checked(transfer_penalty_ctx, penalty, defai_mint.decimals)?;
+            Ok(0)
+        }
+    }
+}
+
+// Credit a penalty already sitting in escrow to all current reward shares at once.
+// With no shares left it just stays in the escrow balance.
+fn distribute_penalty(program_state: &mut ProgramState, amount: u64) -> Result<()> {
+    if amount == 0 || program_state.total_reward_shares == 0 {
+        return Ok(());
+    }
+    
+    let index_delta = (amount as u128)
+        .checked_mul(ACC_REWARD_PRECISION)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(program_state.total_reward_shares as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    
+    program_state.acc_reward_per_share = program_state.acc_reward_per_share
+        .checked_add(index_delta)
+        .ok_or(StakingError::MathOverflow)?;
+    program_state.total_pending_rewards = program_state.total_pending_rewards
+        .checked_add(amount)
+        .ok_or(StakingError::MathOverflow)?;
+    
+    Ok(())
+}
+
+// Linear APY rewards a legacy stake accrued since its last claim, at the old tier rates
+fn legacy_accrued_rewards(legacy: &LegacyUserStake, now: i64) -> Result<u64> {
+    let apy_bps = LEGACY_TIER_APYS
+        .iter()
+        .find(|(min_amount, _)| legacy.staked_amount >= *min_amount)
+        .map(|(_, apy_bps)| *apy_bps)
+        .unwrap_or(0);
+    let elapsed = (now - legacy.last_claim_timestamp).max(0) as u128;
+    let rewards = (legacy.staked_amount as u128)
+        .checked_mul(apy_bps as u128)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_mul(elapsed)
+        .ok_or(StakingError::MathOverflow)?
+        .checked_div(SECONDS_PER_YEAR as u128 * BASIS_POINTS as u128)
+        .ok_or(StakingError::MathOverflow)?;
+    u64::try_from(rewards).map_err(|_| StakingError::MathOverflow.into())
+}
+
+// Loads an account still on its original layout. Accounts already at `current_len`
+// are reported as migrated; any other size is rejected.
+fn read_legacy_account<T: AnchorDeserialize>(
+    info: &AccountInfo,
+    program_id: &Pubkey,
+    discriminator: &[u8; 8],
+    legacy_len: usize,
+    current_len: usize,
+) -> Result<T> {
+    require_keys_eq!(*info.owner, *program_id, StakingError::InvalidAccountLayout);
+    let data = info.try_borrow_data()?;
+    require!(
+        data.len() >= 8 && data[..8] == discriminator[..],
+        StakingError::InvalidAccountLayout
+    );
+    require!(data.len() != 8 + current_len, StakingError::AlreadyMigrated);
+    require!(data.len() == 8 + legacy_len, StakingError::InvalidAccountLayout);
+    
+    T::deserialize(&mut &data[8..]).map_err(|_| StakingError::InvalidAccountLayout.into())
+}
+
+// Grows `info` to `new_len`, topping up rent from `payer`, and writes `account` into it
+fn write_migrated_account<'info, T: AccountSerialize>(
+    info: &AccountInfo<'info>,
+    account: &T,
+    new_len: usize,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let rent_due = Rent::get()?.minimum_balance(new_len).saturating_sub(info.lamports());
+    if rent_due > 0 {
+        let transfer_ctx = CpiContext::new(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: payer.to_account_info(),
+                to: info.clone(),
+            },
+        );
+        anchor_lang::system_program::transfer(transfer_ctx, rent_due)?;
+    }
+    info.realloc(new_len, false)?;
+    
+    let mut data = info.try_borrow_mut_data()?;
+    let mut writer: &mut [u8] = &mut data[..];
+    account.try_serialize(&mut writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zeroed_account<T: Discriminator>(len: usize) -> Vec<u8> {
+        let mut data = T::DISCRIMINATOR.to_vec();
+        data.resize(8 + len, 0);
+        data
+    }
+
+    #[test]
+    fn legacy_user_stake_is_not_migrated() {
+        let data = zeroed_account::<UserStake>(LEGACY_USER_STAKE_LEN);
+        let err = UserStake::try_deserialize(&mut &data[..]).err().unwrap();
+        assert_eq!(err, StakingError::AccountNotMigrated.into());
+    }
+
+    #[test]
+    fn outdated_version_is_not_migrated() {
+        let data = zeroed_account::<UserStake>(UserStake::LEN);
+        let err = UserStake::try_deserialize(&mut &data[..]).err().unwrap();
+        assert_eq!(err, StakingError::AccountNotMigrated.into());
+
+        let data = zeroed_account::<ProgramState>(ProgramState::LEN);
+        let err = ProgramState::try_deserialize(&mut &data[..]).err().unwrap();
+        assert_eq!(err, StakingError::AccountNotMigrated.into());
+
+        let data = zeroed_account::<RewardEscrow>(LEGACY_REWARD_ESCROW_LEN);
+        let err = RewardEscrow::try_deserialize(&mut &data[..]).err().unwrap();
+        assert_eq!(err, StakingError::AccountNotMigrated.into());
+    }
+
+    #[test]
+    fn current_user_stake_round_trips() {
+        let data = zeroed_account::<UserStake>(UserStake::LEN);
+        let mut user_stake = UserStake::try_deserialize_unchecked(&mut &data[..]).unwrap();
+        user_stake.version = USER_STAKE_VERSION;
+        user_stake.staked_amount = 42;
+
+        let mut data = Vec::new();
+        user_stake.try_serialize(&mut data).unwrap();
+        data.resize(8 + UserStake::LEN, 0);
+        let loaded = UserStake::try_deserialize(&mut &data[..]).unwrap();
+        assert!(loaded.is_current());
+        assert_eq!(loaded.staked_amount, 42);
+    }
+
+    #[test]
+    fn wrong_discriminator_is_rejected() {
+        let data = zeroed_account::<RewardEscrow>(UserStake::LEN);
+        let err = UserStake::try_deserialize(&mut &data[..]).err().unwrap();
+        assert_eq!(err, ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+}
@@ -0,0 +1,52 @@
+// Client for programs that read a wallet's staking tier through CPI (`cpi` feature).
+//
+// Depend on this crate with `features = ["cpi"]` and call `get_user_tier` with the accounts
+// from the `*_address` helpers. The accounts must be this program's PDAs for the wallet, and
+// the result is only accepted if the return data came from this program and describes the
+// wallet that was asked about.
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::get_return_data;
+
+use crate::{StakingError, UserTier};
+
+pub fn program_state_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"program-state"], &crate::ID).0
+}
+
+pub fn tier_config_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"tier-config", program_state_address().as_ref()], &crate::ID).0
+}
+
+pub fn user_stake_address(owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"user-stake", owner.as_ref()], &crate::ID).0
+}
+
+pub fn get_user_tier<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, crate::cpi::accounts::GetUserTier<'info>>,
+) -> Result<UserTier> {
+    require_keys_eq!(ctx.program.key(), crate::ID, StakingError::InvalidTierResponse);
+    let owner = ctx.accounts.owner.key();
+    
+    // Reject substituted accounts before calling out
+    let accounts = &ctx.accounts;
+    require_keys_eq!(accounts.program_state.key(), program_state_address(), StakingError::InvalidTierResponse);
+    require_keys_eq!(accounts.tier_config.key(), tier_config_address(), StakingError::InvalidTierResponse);
+    require_keys_eq!(accounts.user_stake.key(), user_stake_address(&owner), StakingError::InvalidTierResponse);
+    for info in [&accounts.program_state, &accounts.tier_config] {
+        require_keys_eq!(*info.owner, crate::ID, StakingError::InvalidTierResponse);
+    }
+    require!(
+        accounts.user_stake.data_is_empty() || *accounts.user_stake.owner == crate::ID,
+        StakingError::InvalidTierResponse
+    );
+    
+    crate::cpi::get_user_tier(ctx)?;
+    
+    let (program_id, data) = get_return_data().ok_or(StakingError::InvalidTierResponse)?;
+    require_keys_eq!(program_id, crate::ID, StakingError::InvalidTierResponse);
+    let user_tier = UserTier::try_from_slice(&data)
+        .map_err(|_| StakingError::InvalidTierResponse)?;
+    require_keys_eq!(user_tier.owner, owner, StakingError::InvalidTierResponse);
+    
+    Ok(user_tier)
+}
@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
+    associated_token::AssociatedToken,
     token::{self, Token, TokenAccount, Transfer},
     token_2022::{self as token22, Token2022},
     token_interface::{TokenAccount as TokenAccount2022, TransferChecked, Burn, CloseAccount},
@@ -12,6 +13,9 @@ use randomness::*;
 pub mod vrf;
 use vrf::*;
 
+pub mod nft;
+use nft::*;
+
 declare_id!("DB9Zvhdp5xh853d2Tr2HBkRDDaCSioD7vwchhcGaXCw3");
 
 // Tax configuration constants (basis points = parts per 10_000)
@@ -23,8 +27,8 @@ const TAX_RESET_DURATION: i64 = 24 * 60 * 60; // 24 hours in seconds
 // Timelock constants
 const ADMIN_TIMELOCK_DURATION: i64 = 48 * 60 * 60; // 48 hours for admin actions
 
-// OG NFT Whitelist Merkle Root
-const WHITELIST_ROOT: [u8; 32] = [75, 45, 118, 95, 221, 195, 106, 5, 187, 186, 56, 74, 112, 138, 19, 108, 59, 243, 44, 140, 228, 10, 199, 125, 41, 242, 223, 102, 191, 115, 73, 142];
+// Distribution id reported for claims against the original hard-wired roots
+const LEGACY_DISTRIBUTION_ID: u64 = 0;
 
 // Vesting constants
 const VESTING_DURATION: i64 = 90 * 24 * 60 * 60; // 90 days in seconds
@@ -92,66 +96,135 @@ pub mod defai_swap {
         Ok(())
     }
 
-    pub fn update_prices(ctx: Context<UpdateConfig>, prices: Vec<u64>) -> Result<()> {
-        require!(prices.len() == 5, ErrorCode::InvalidInput);
+    // Price, treasury, supply, root, distribution activation, VRF and pause changes go
+    // through a timelocked proposal; only the instant `pause` bypasses it, as the emergency stop
+    pub fn propose_timelock(
+        ctx: Context<ProposeTimelock>,
+        id: u64,
+        proposal_type: ProposalType,
+    ) -> Result<()> {
         require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
         
-        let cfg = &mut ctx.accounts.config;
-        cfg.prices = [prices[0], prices[1], prices[2], prices[3], prices[4]];
+        let now = Clock::get()?.unix_timestamp;
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.id = id;
+        proposal.proposer = ctx.accounts.admin.key();
+        proposal.proposal_type = proposal_type.clone();
+        proposal.execute_after = now + ADMIN_TIMELOCK_DURATION;
+        proposal.executed = false;
+        proposal.cancelled = false;
+        
+        msg!("Timelock proposal {} can be executed after {}", id, proposal.execute_after);
+        
+        emit!(TimelockProposed {
+            id,
+            proposer: ctx.accounts.admin.key(),
+            proposal_type,
+            execute_after: proposal.execute_after,
+            timestamp: now,
+        });
         
         Ok(())
     }
 
-    pub fn update_treasury(ctx: Context<UpdateConfig>, new_treasury: Pubkey) -> Result<()> {
+    pub fn execute_timelock(ctx: Context<ExecuteTimelock>, id: u64) -> Result<()> {
         require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
         
+        let now = Clock::get()?.unix_timestamp;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed && !proposal.cancelled, ErrorCode::ProposalNotPending);
+        require!(now >= proposal.execute_after, ErrorCode::TimelockNotExpired);
+        
         let cfg = &mut ctx.accounts.config;
-        cfg.treasury = new_treasury;
+        let collection_config = &mut ctx.accounts.collection_config;
+        match proposal.proposal_type.clone() {
+            ProposalType::UpdatePrices { prices } => {
+                cfg.prices = prices;
+            }
+            ProposalType::UpdateTreasury { new_treasury } => {
+                cfg.treasury = new_treasury;
+                collection_config.treasury = new_treasury;
+            }
+            ProposalType::UpdateTierSupplies { supplies } => {
+                // Supplies can't drop below what has already been minted
+                for (tier, supply) in supplies.iter().enumerate() {
+                    require!(*supply >= collection_config.tier_minted[tier], ErrorCode::InvalidInput);
+                }
+                // Tier 0 must still hold the whole OG reserve on top of the public mints
+                let public_tier_0_supply = supplies[0]
+                    .checked_sub(collection_config.og_tier_0_supply)
+                    .ok_or(ErrorCode::InvalidInput)?;
+                require!(public_tier_0_supply >= collection_config.tier_minted[0], ErrorCode::InvalidInput);
+                collection_config.tier_supplies = supplies;
+            }
+            ProposalType::UpdateDistributionRoot { distribution_id, merkle_root, total_amount } => {
+                let distribution = ctx.accounts.distribution
+                    .as_mut()
+                    .ok_or(ErrorCode::MissingDistribution)?;
+                require!(distribution.id == distribution_id, ErrorCode::MissingDistribution);
+                require!(total_amount >= distribution.claimed_amount, ErrorCode::InvalidInput);
+                distribution.merkle_root = merkle_root;
+                distribution.total_amount = total_amount;
+            }
+            ProposalType::ActivateDistribution { distribution_id } => {
+                let distribution = ctx.accounts.distribution
+                    .as_mut()
+                    .ok_or(ErrorCode::MissingDistribution)?;
+                require!(distribution.id == distribution_id, ErrorCode::MissingDistribution);
+                distribution.active = true;
+            }
+            ProposalType::SetVrfEnabled { enabled } => {
+                cfg.vrf_enabled = enabled;
+            }
+            ProposalType::SetPaused { paused } => {
+                cfg.paused = paused;
+            }
+        }
+        proposal.executed = true;
+        
+        emit!(TimelockExecuted {
+            id,
+            executor: ctx.accounts.admin.key(),
+            proposal_type: proposal.proposal_type.clone(),
+            timestamp: now,
+        });
         
         Ok(())
     }
 
-    pub fn pause(ctx: Context<UpdateConfig>) -> Result<()> {
+    pub fn cancel_timelock(ctx: Context<CancelTimelock>, id: u64) -> Result<()> {
         require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
-        require!(!ctx.accounts.config.paused, ErrorCode::AlreadyPaused);
         
-        ctx.accounts.config.paused = true;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed && !proposal.cancelled, ErrorCode::ProposalNotPending);
+        proposal.cancelled = true;
         
-        // Emit admin action event
-        emit!(AdminAction {
-            admin: ctx.accounts.admin.key(),
-            action: "Pause protocol".to_string(),
+        emit!(TimelockCancelled {
+            id,
+            canceller: ctx.accounts.admin.key(),
             timestamp: Clock::get()?.unix_timestamp,
         });
         
         Ok(())
     }
 
-    pub fn unpause(ctx: Context<UpdateConfig>) -> Result<()> {
+    // Emergency stop; unpausing goes through a SetPaused proposal
+    pub fn pause(ctx: Context<UpdateConfig>) -> Result<()> {
         require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
-        require!(ctx.accounts.config.paused, ErrorCode::NotPaused);
+        require!(!ctx.accounts.config.paused, ErrorCode::AlreadyPaused);
         
-        ctx.accounts.config.paused = false;
+        ctx.accounts.config.paused = true;
         
         // Emit admin action event
         emit!(AdminAction {
             admin: ctx.accounts.admin.key(),
-            action: "Unpause protocol".to_string(),
+            action: "Pause protocol".to_string(),
             timestamp: Clock::get()?.unix_timestamp,
         });
         
         Ok(())
     }
 
-    pub fn initialize_whitelist(ctx: Context<InitializeWhitelist>) -> Result<()> {
-        require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
-        
-        let whitelist = &mut ctx.accounts.whitelist;
-        whitelist.root = WHITELIST_ROOT;
-        whitelist.claimed_count = 0;
-        Ok(())
-    }
-    
     pub fn propose_admin_change(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
         require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
         
@@ -204,25 +277,6 @@ pub mod defai_swap {
         Ok(())
     }
     
-    pub fn enable_vrf(ctx: Context<UpdateConfig>) -> Result<()> {
-        require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
-        require!(!ctx.accounts.config.vrf_enabled, ErrorCode::VrfAlreadyEnabled);
-        
-        let cfg = &mut ctx.accounts.config;
-        cfg.vrf_enabled = true;
-        
-        msg!("VRF enabled for swap program");
-        
-        // Emit admin action event
-        emit!(AdminAction {
-            admin: ctx.accounts.admin.key(),
-            action: "Enable VRF".to_string(),
-            timestamp: Clock::get()?.unix_timestamp,
-        });
-        
-        Ok(())
-    }
-    
     pub fn request_vrf_randomness(ctx: Context<RequestRandomness>) -> Result<()> {
         vrf::request_randomness(ctx)
     }
@@ -271,8 +325,6 @@ pub mod defai_swap {
         tier_prices: [u64; 5],
         tier_supplies: [u16; 5],
         tier_uri_prefixes: Vec<String>,
-        og_tier_0_merkle_root: [u8; 32],  // For MAY20DEFAIHolders.csv - NFT minting with 1:1 vesting
-        airdrop_merkle_root: [u8; 32],    // For 10_1AIR-Sheet1.csv - Pure vesting, no NFT
         og_tier_0_supply: u16,            // Reserved supply for OG holders
     ) -> Result<()> {
         let collection_config = &mut ctx.accounts.collection_config;
@@ -291,22 +343,106 @@ pub mod defai_swap {
         collection_config.tier_prices = tier_prices;
         collection_config.tier_supplies = tier_supplies;
         collection_config.tier_minted = [0; 5];
-        collection_config.og_tier_0_merkle_root = og_tier_0_merkle_root;  // MAY20DEFAIHolders merkle root
-        collection_config.airdrop_merkle_root = airdrop_merkle_root;      // 10_1AIR merkle root
         collection_config.og_tier_0_supply = og_tier_0_supply;            // Reserved supply for OG holders
         collection_config.og_tier_0_minted = 0;                          // Initialize OG claims counter
         
         Ok(())
     }
 
-    /// Function 1: For MAY20DEFAIHolders.csv - Mints NFT and provides 1:1 vesting from Quantity column
+    /// Creates `collection_mint` as a Token-2022 group with the escrow PDA as update
+    /// authority. Must run before any v6 swap, since every bonus NFT joins the group.
+    pub fn initialize_collection_group(ctx: Context<InitializeCollectionGroup>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
+        
+        // Tier supplies already cap the collection and can be raised by timelock,
+        // so the group itself is left unbounded
+        let max_size = u32::MAX;
+        let escrow_seeds = &[b"escrow" as &[u8], &[ctx.accounts.escrow.bump][..]];
+        create_collection_group(
+            CollectionGroupAccounts {
+                payer: &ctx.accounts.admin.to_account_info(),
+                collection_mint: &ctx.accounts.collection_mint.to_account_info(),
+                authority: &ctx.accounts.escrow.to_account_info(),
+                token_program: &ctx.accounts.token_program_2022.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+            },
+            max_size,
+            &[&escrow_seeds[..]],
+        )?;
+        
+        emit!(CollectionGroupInitialized {
+            collection_mint: ctx.accounts.collection_mint.key(),
+            update_authority: ctx.accounts.escrow.key(),
+            max_size,
+        });
+        
+        Ok(())
+    }
+
+    /// Creates a Merkle distribution round (OG tier 0 NFT claims or vesting-only airdrops).
+    /// It stays closed until an ActivateDistribution proposal is executed.
+    pub fn create_distribution(
+        ctx: Context<CreateDistribution>,
+        id: u64,
+        params: DistributionParams,
+    ) -> Result<()> {
+        require_keys_eq!(ctx.accounts.admin.key(), ctx.accounts.config.admin, ErrorCode::Unauthorized);
+        require!(id != LEGACY_DISTRIBUTION_ID, ErrorCode::InvalidInput);
+        require!(params.total_amount > 0, ErrorCode::InvalidInput);
+        require!(params.end_timestamp > params.start_timestamp, ErrorCode::InvalidInput);
+        require!(
+            params.vesting_duration > 0
+                && params.cliff_duration >= 0
+                && params.cliff_duration <= params.vesting_duration,
+            ErrorCode::InvalidVestingSchedule
+        );
+        // NFT vesting always uses the protocol cliff in claim_vested_v6
+        require!(
+            params.kind == DistributionKind::Airdrop || params.cliff_duration == CLIFF_DURATION,
+            ErrorCode::InvalidVestingSchedule
+        );
+        // Only airdrops have a legacy claim list to reissue
+        require!(
+            params.kind == DistributionKind::Airdrop || !params.reissues_legacy_airdrop,
+            ErrorCode::WrongDistributionKind
+        );
+        
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.id = id;
+        distribution.kind = params.kind;
+        distribution.merkle_root = params.merkle_root;
+        distribution.total_amount = params.total_amount;
+        distribution.claimed_amount = 0;
+        distribution.claimed_count = 0;
+        distribution.cliff_duration = params.cliff_duration;
+        distribution.vesting_duration = params.vesting_duration;
+        distribution.start_timestamp = params.start_timestamp;
+        distribution.end_timestamp = params.end_timestamp;
+        distribution.reissues_legacy_airdrop = params.reissues_legacy_airdrop;
+        distribution.active = false;
+        distribution.bump = ctx.bumps.distribution;
+        
+        emit!(DistributionCreated {
+            id,
+            kind: params.kind,
+            merkle_root: params.merkle_root,
+            total_amount: params.total_amount,
+            cliff_duration: params.cliff_duration,
+            vesting_duration: params.vesting_duration,
+            start_timestamp: params.start_timestamp,
+            end_timestamp: params.end_timestamp,
+            reissues_legacy_airdrop: params.reissues_legacy_airdrop,
+        });
+        
+        Ok(())
+    }
+
+    /// Function 1: For OG tier 0 distributions (e.g. MAY20DEFAIHolders.csv) - Mints NFT and provides 1:1 vesting from Quantity column
     pub fn swap_og_tier0_for_pnft_v6(
         ctx: Context<SwapOgTier0ForPnftV6>,
-        vesting_amount: u64,  // The Quantity from MAY20DEFAIHolders.csv for 1:1 vesting
+        distribution_id: u64,
+        vesting_amount: u64,  // The Quantity from the distribution CSV for 1:1 vesting
         merkle_proof: Vec<[u8; 32]>,
-        _metadata_uri: String,
-        _name: String,
-        _symbol: String,
     ) -> Result<()> {
         msg!("=== SWAP OG TIER 0 FOR PNFT V6 START ===");
         require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
@@ -315,29 +451,36 @@ pub mod defai_swap {
         let og_claim = &mut ctx.accounts.og_tier0_claim;
         let clock = Clock::get()?;
         
-        // For MAY20DEFAIHolders.csv: OG Tier 0 holders mint NFT and get 1:1 vesting
-        // Verify user hasn't already claimed their OG tier 0 NFT
+        // OG Tier 0 holders mint NFT and get 1:1 vesting
+        // Verify user hasn't already claimed their OG tier 0 NFT in any distribution
         require!(!og_claim.claimed, ErrorCode::OgTier0AlreadyClaimed);
         
+        let distribution = &mut ctx.accounts.distribution;
+        require!(distribution.kind == DistributionKind::OgTier0, ErrorCode::WrongDistributionKind);
+        require!(
+            distribution.active
+                && clock.unix_timestamp >= distribution.start_timestamp
+                && clock.unix_timestamp <= distribution.end_timestamp,
+            ErrorCode::DistributionNotActive
+        );
+        
         // Verify merkle proof for OG tier 0 whitelist
-        let user_key = ctx.accounts.user.key();
-        let amount_bytes = vesting_amount.to_le_bytes();
-        let leaf_data = [user_key.as_ref(), &amount_bytes].concat();
-        let leaf = solana_program::keccak::hash(&leaf_data);
-        
-        let is_valid = merkle_proof.iter().fold(leaf.0, |acc, proof_elem| {
-            let mut combined = vec![];
-            if acc <= *proof_elem {
-                combined.extend_from_slice(&acc);
-                combined.extend_from_slice(proof_elem);
-            } else {
-                combined.extend_from_slice(proof_elem);
-                combined.extend_from_slice(&acc);
-            }
-            solana_program::keccak::hash(&combined).0
-        }) == config.og_tier_0_merkle_root;
+        require!(
+            defai_merkle::verify_proof(&ctx.accounts.user.key(), vesting_amount, &merkle_proof, &distribution.merkle_root),
+            ErrorCode::NotOnOgWhitelist
+        );
         
-        require!(is_valid, ErrorCode::NotOnOgWhitelist);
+        distribution.claimed_amount = distribution.claimed_amount
+            .checked_add(vesting_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            distribution.claimed_amount <= distribution.total_amount,
+            ErrorCode::DistributionExhausted
+        );
+        distribution.claimed_count = distribution.claimed_count
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let vesting_duration = distribution.vesting_duration;
         
         // Check OG tier 0 supply (separate from regular tier 0)
         require!(
@@ -367,13 +510,33 @@ pub mod defai_swap {
         };
         let random_bonus = calculate_random_bonus(random_value, min_bonus, max_bonus);
         
+        // Create the NFT with its on-chain metadata; OG claims number their own range
+        let serial = config.og_tier_0_minted.saturating_add(1);
+        let metadata = og_metadata(config, serial, ctx.accounts.nft_mint.key(), ctx.accounts.escrow.key())?;
+        let escrow_seeds = &[b"escrow" as &[u8], &[ctx.accounts.escrow.bump][..]];
+        mint_bonus_nft(
+            BonusNftAccounts {
+                payer: &ctx.accounts.user.to_account_info(),
+                owner: &ctx.accounts.user.to_account_info(),
+                nft_mint: &ctx.accounts.nft_mint.to_account_info(),
+                collection_mint: &ctx.accounts.collection_mint.to_account_info(),
+                nft_token_account: &ctx.accounts.nft_token_account.to_account_info(),
+                authority: &ctx.accounts.escrow.to_account_info(),
+                token_program: &ctx.accounts.token_program_2022.to_account_info(),
+                associated_token_program: &ctx.accounts.associated_token_program.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+            },
+            &metadata,
+            &[&escrow_seeds[..]],
+        )?;
+        
         // Set up bonus state
         let bonus_state = &mut ctx.accounts.bonus_state;
         bonus_state.mint = ctx.accounts.nft_mint.key();
         bonus_state.tier = 0;
         bonus_state.bonus_bps = random_bonus;
         bonus_state.vesting_start = clock.unix_timestamp;
-        bonus_state.vesting_duration = VESTING_DURATION;
+        bonus_state.vesting_duration = vesting_duration;
         bonus_state.claimed = false;
         bonus_state.fee_deducted = 0;
         
@@ -383,7 +546,7 @@ pub mod defai_swap {
         vesting_state.total_amount = vesting_amount;
         vesting_state.released_amount = 0;
         vesting_state.start_timestamp = clock.unix_timestamp;
-        vesting_state.end_timestamp = clock.unix_timestamp + VESTING_DURATION;
+        vesting_state.end_timestamp = clock.unix_timestamp + vesting_duration;
         vesting_state.last_claimed_timestamp = clock.unix_timestamp;
         
         // Mark as claimed for this user
@@ -405,6 +568,14 @@ pub mod defai_swap {
             timestamp: clock.unix_timestamp,
         });
         
+        emit!(OgTier0Claimed {
+            user: ctx.accounts.user.key(),
+            distribution_id,
+            nft_mint: ctx.accounts.nft_mint.key(),
+            vesting_amount,
+            timestamp: clock.unix_timestamp,
+        });
+        
         msg!("=== SWAP OG TIER 0 FOR PNFT V6 COMPLETE ===");
         Ok(())
     }
@@ -412,9 +583,6 @@ pub mod defai_swap {
     pub fn swap_defai_for_pnft_v6(
         ctx: Context<SwapDefaiForPnftV6>,
         tier: u8,
-        _metadata_uri: String,
-        _name: String,
-        _symbol: String,
     ) -> Result<()> {
         msg!("=== SWAP DEFAI FOR PNFT V6 START ===");
         require!(tier < 5, ErrorCode::InvalidTier);
@@ -498,6 +666,26 @@ pub mod defai_swap {
         };
         let random_bonus = calculate_random_bonus(random_value, min_bonus, max_bonus);
         
+        // Create the NFT with its on-chain metadata
+        let serial = config.tier_minted[tier as usize].saturating_add(1);
+        let metadata = tier_metadata(config, tier, serial, ctx.accounts.nft_mint.key(), ctx.accounts.escrow.key())?;
+        let escrow_seeds = &[b"escrow" as &[u8], &[ctx.accounts.escrow.bump][..]];
+        mint_bonus_nft(
+            BonusNftAccounts {
+                payer: &ctx.accounts.user.to_account_info(),
+                owner: &ctx.accounts.user.to_account_info(),
+                nft_mint: &ctx.accounts.nft_mint.to_account_info(),
+                collection_mint: &ctx.accounts.collection_mint.to_account_info(),
+                nft_token_account: &ctx.accounts.nft_token_account.to_account_info(),
+                authority: &ctx.accounts.escrow.to_account_info(),
+                token_program: &ctx.accounts.token_program_2022.to_account_info(),
+                associated_token_program: &ctx.accounts.associated_token_program.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+            },
+            &metadata,
+            &[&escrow_seeds[..]],
+        )?;
+        
         // Set up bonus state
         let bonus_state = &mut ctx.accounts.bonus_state;
         bonus_state.mint = ctx.accounts.nft_mint.key();
@@ -550,9 +738,6 @@ pub mod defai_swap {
     pub fn swap_old_defai_for_pnft_v6(
         ctx: Context<SwapOldDefaiForPnftV6>,
         tier: u8,
-        _metadata_uri: String,
-        _name: String,
-        _symbol: String,
     ) -> Result<()> {
         msg!("=== SWAP OLD DEFAI FOR PNFT V6 START ===");
         require!(tier < 5, ErrorCode::InvalidTier);
@@ -611,6 +796,26 @@ pub mod defai_swap {
         };
         let random_bonus = calculate_random_bonus(random_value, min_bonus, max_bonus);
         
+        // Create the NFT with its on-chain metadata
+        let serial = config.tier_minted[tier as usize].saturating_add(1);
+        let metadata = tier_metadata(config, tier, serial, ctx.accounts.nft_mint.key(), ctx.accounts.escrow.key())?;
+        let escrow_seeds = &[b"escrow" as &[u8], &[ctx.accounts.escrow.bump][..]];
+        mint_bonus_nft(
+            BonusNftAccounts {
+                payer: &ctx.accounts.user.to_account_info(),
+                owner: &ctx.accounts.user.to_account_info(),
+                nft_mint: &ctx.accounts.nft_mint.to_account_info(),
+                collection_mint: &ctx.accounts.collection_mint.to_account_info(),
+                nft_token_account: &ctx.accounts.nft_token_account.to_account_info(),
+                authority: &ctx.accounts.escrow.to_account_info(),
+                token_program: &ctx.accounts.token_program_2022.to_account_info(),
+                associated_token_program: &ctx.accounts.associated_token_program.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+            },
+            &metadata,
+            &[&escrow_seeds[..]],
+        )?;
+        
         // Set up bonus state
         let bonus_state = &mut ctx.accounts.bonus_state;
         bonus_state.mint = ctx.accounts.nft_mint.key();
@@ -740,64 +945,78 @@ pub mod defai_swap {
         Ok(())
     }
 
-    /// Function 2: For 10_1AIR-Sheet1.csv - NO NFT minting, only vesting of AIRDROP column amount
+    /// Function 2: For airdrop distributions (e.g. 10_1AIR-Sheet1.csv) - NO NFT minting, only vesting of AIRDROP column amount
     /// This is separate from OG tier 0 and doesn't involve any NFT minting
     pub fn claim_airdrop(
         ctx: Context<ClaimAirdrop>,
-        amount: u64,  // The AIRDROP column amount from 10_1AIR-Sheet1.csv
+        distribution_id: u64,
+        amount: u64,  // The AIRDROP column amount from the distribution CSV
         merkle_proof: Vec<[u8; 32]>,
     ) -> Result<()> {
         msg!("=== CLAIM AIRDROP START (10:1 Air Recipients - No NFT) ===");
         require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
         
         let airdrop_vesting = &mut ctx.accounts.airdrop_vesting;
+        let distribution = &mut ctx.accounts.distribution;
         let clock = Clock::get()?;
-        let config = &ctx.accounts.collection_config;
         
         // Verify user hasn't already claimed
         require!(airdrop_vesting.beneficiary == Pubkey::default(), ErrorCode::AlreadyClaimed);
         
+        require!(distribution.kind == DistributionKind::Airdrop, ErrorCode::WrongDistributionKind);
+        check_legacy_airdrop_claim(
+            distribution.reissues_legacy_airdrop,
+            !ctx.accounts.legacy_airdrop_vesting.data_is_empty(),
+        )?;
+        require!(
+            distribution.active
+                && clock.unix_timestamp >= distribution.start_timestamp
+                && clock.unix_timestamp <= distribution.end_timestamp,
+            ErrorCode::DistributionNotActive
+        );
+        
         // Verify merkle proof
-        let user_key = ctx.accounts.user.key();
-        let amount_bytes = amount.to_le_bytes();
-        let leaf_data = [user_key.as_ref(), &amount_bytes].concat();
-        let leaf = solana_program::keccak::hash(&leaf_data);
-        
-        let is_valid = merkle_proof.iter().fold(leaf.0, |acc, proof_elem| {
-            let mut combined = vec![];
-            if acc <= *proof_elem {
-                combined.extend_from_slice(&acc);
-                combined.extend_from_slice(proof_elem);
-            } else {
-                combined.extend_from_slice(proof_elem);
-                combined.extend_from_slice(&acc);
-            }
-            solana_program::keccak::hash(&combined).0
-        }) == config.airdrop_merkle_root;
+        require!(
+            defai_merkle::verify_proof(&ctx.accounts.user.key(), amount, &merkle_proof, &distribution.merkle_root),
+            ErrorCode::InvalidMerkleProof
+        );
         
-        require!(is_valid, ErrorCode::InvalidMerkleProof);
+        distribution.claimed_amount = distribution.claimed_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            distribution.claimed_amount <= distribution.total_amount,
+            ErrorCode::DistributionExhausted
+        );
+        distribution.claimed_count = distribution.claimed_count
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
         
-        // Initialize vesting state
+        // Initialize vesting state from the distribution's schedule
+        let vesting_end = clock.unix_timestamp + distribution.vesting_duration;
         airdrop_vesting.beneficiary = ctx.accounts.user.key();
+        airdrop_vesting.distribution_id = distribution_id;
         airdrop_vesting.total_amount = amount;
         airdrop_vesting.released_amount = 0;
         airdrop_vesting.start_timestamp = clock.unix_timestamp;
-        airdrop_vesting.end_timestamp = clock.unix_timestamp + VESTING_DURATION;
+        airdrop_vesting.cliff_timestamp = clock.unix_timestamp + distribution.cliff_duration;
+        airdrop_vesting.end_timestamp = vesting_end;
         airdrop_vesting.last_claimed_timestamp = clock.unix_timestamp;
         
         // Emit event
         emit!(AirdropClaimed {
             user: ctx.accounts.user.key(),
+            distribution_id,
             amount,
             vesting_start: clock.unix_timestamp,
-            vesting_end: clock.unix_timestamp + VESTING_DURATION,
+            vesting_end,
         });
         
         msg!("=== CLAIM AIRDROP COMPLETE ===");
         Ok(())
     }
 
-    pub fn claim_vested_airdrop(ctx: Context<ClaimVestedAirdrop>) -> Result<()> {
+    pub fn claim_vested_airdrop(ctx: Context<ClaimVestedAirdrop>, distribution_id: u64) -> Result<()> {
         msg!("=== CLAIM VESTED AIRDROP START ===");
         require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
         
@@ -805,22 +1024,15 @@ pub mod defai_swap {
         let now = Clock::get()?.unix_timestamp;
         
         // Check cliff period
-        let cliff_end = airdrop_vesting.start_timestamp + CLIFF_DURATION;
-        require!(now >= cliff_end, ErrorCode::StillInCliff);
+        require!(now >= airdrop_vesting.cliff_timestamp, ErrorCode::StillInCliff);
         
         // Calculate vested amount
-        let elapsed = now - airdrop_vesting.start_timestamp;
-        let duration = airdrop_vesting.end_timestamp - airdrop_vesting.start_timestamp;
-        
-        let vested_amount = if elapsed >= duration {
-            airdrop_vesting.total_amount
-        } else {
-            (airdrop_vesting.total_amount as u128)
-                .checked_mul(elapsed as u128)
-                .ok_or(ErrorCode::MathOverflow)?
-                .checked_div(duration as u128)
-                .ok_or(ErrorCode::MathOverflow)? as u64
-        };
+        let vested_amount = linear_vested_amount(
+            airdrop_vesting.total_amount,
+            airdrop_vesting.start_timestamp,
+            airdrop_vesting.end_timestamp,
+            now,
+        )?;
         
         let claimable = vested_amount.saturating_sub(airdrop_vesting.released_amount);
         require!(claimable > 0, ErrorCode::NothingToClaim);
@@ -848,6 +1060,7 @@ pub mod defai_swap {
         // Emit event
         emit!(AirdropVestingClaimed {
             user: ctx.accounts.user.key(),
+            distribution_id,
             amount_claimed: claimable,
             total_vested: vested_amount,
             timestamp: now,
@@ -857,6 +1070,56 @@ pub mod defai_swap {
         Ok(())
     }
 
+    /// Claims vesting created against the original airdrop root, before distributions
+    /// existed. Those accounts keep their old seeds, layout and protocol cliff.
+    pub fn claim_vested_airdrop_legacy(ctx: Context<ClaimVestedAirdropLegacy>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+        
+        let airdrop_vesting = &mut ctx.accounts.airdrop_vesting;
+        let now = Clock::get()?.unix_timestamp;
+        
+        let cliff_end = airdrop_vesting.start_timestamp + CLIFF_DURATION;
+        require!(now >= cliff_end, ErrorCode::StillInCliff);
+        
+        let vested_amount = linear_vested_amount(
+            airdrop_vesting.total_amount,
+            airdrop_vesting.start_timestamp,
+            airdrop_vesting.end_timestamp,
+            now,
+        )?;
+        
+        let claimable = vested_amount.saturating_sub(airdrop_vesting.released_amount);
+        require!(claimable > 0, ErrorCode::NothingToClaim);
+        
+        let escrow_seeds = &[b"escrow" as &[u8], &[ctx.accounts.escrow.bump][..]];
+        let signer_seeds = &[&escrow_seeds[..]];
+        
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.user_defai_ata.to_account_info(),
+                authority: ctx.accounts.escrow.to_account_info(),
+                mint: ctx.accounts.defai_mint.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token22::transfer_checked(cpi_ctx, claimable, 6)?;
+        
+        airdrop_vesting.released_amount += claimable;
+        airdrop_vesting.last_claimed_timestamp = now;
+        
+        emit!(AirdropVestingClaimed {
+            user: ctx.accounts.user.key(),
+            distribution_id: LEGACY_DISTRIBUTION_ID,
+            amount_claimed: claimable,
+            total_vested: vested_amount,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+
     pub fn claim_vested_v6(ctx: Context<ClaimVestedV6>) -> Result<()> {
         msg!("=== CLAIM VESTED V6 START ===");
         require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
@@ -1131,24 +1394,66 @@ pub mod defai_swap {
             (duration - elapsed) / (24 * 60 * 60)
         };
         
-        msg!("NFT Metadata Update for {}", ctx.accounts.nft_mint.key());
-        msg!("Tier: {}", bonus_state.tier);
-        msg!("Bonus: {}%", bonus_state.bonus_bps as f64 / 100.0);
-        msg!("Redeemed: {}", bonus_state.claimed);
-        msg!("Fee Deducted: {} DEFAI", bonus_state.fee_deducted);
-        msg!("Vesting Total: {} DEFAI", vesting_state.total_amount);
-        msg!("Vesting Released: {} DEFAI", vesting_state.released_amount);
-        msg!("Vesting Remaining: {} DEFAI", remaining_vested);
-        msg!("Days Remaining: {}", days_remaining);
-        
-        // Note: Actual metadata update would require Token Metadata Program CPI
-        // This instruction logs the data that should be included in metadata
+        // Anyone can refresh the attributes; the escrow PDA signs as update authority
+        let escrow_seeds = &[b"escrow" as &[u8], &[ctx.accounts.escrow.bump][..]];
+        update_metadata_fields(
+            MetadataUpdateAccounts {
+                payer: &ctx.accounts.payer.to_account_info(),
+                nft_mint: &ctx.accounts.nft_mint.to_account_info(),
+                authority: &ctx.accounts.escrow.to_account_info(),
+                token_program: &ctx.accounts.token_program_2022.to_account_info(),
+                system_program: &ctx.accounts.system_program.to_account_info(),
+            },
+            &[
+                (BONUS_BPS_KEY, bonus_state.bonus_bps.to_string()),
+                (REDEEMED_KEY, bonus_state.claimed.to_string()),
+                (FEE_DEDUCTED_KEY, bonus_state.fee_deducted.to_string()),
+                (VESTING_TOTAL_KEY, vesting_state.total_amount.to_string()),
+                (VESTING_RELEASED_KEY, vesting_state.released_amount.to_string()),
+                (VESTING_CLAIMABLE_KEY, remaining_vested.to_string()),
+                (DAYS_REMAINING_KEY, days_remaining.to_string()),
+            ],
+            &[&escrow_seeds[..]],
+        )?;
+        
+        emit!(NftMetadataUpdated {
+            nft_mint: ctx.accounts.nft_mint.key(),
+            tier: bonus_state.tier,
+            bonus_bps: bonus_state.bonus_bps,
+            redeemed: bonus_state.claimed,
+            vesting_total: vesting_state.total_amount,
+            vesting_released: vesting_state.released_amount,
+            vesting_claimable: remaining_vested,
+            days_remaining,
+            timestamp: clock.unix_timestamp,
+        });
         
         msg!("=== UPDATE NFT METADATA V6 COMPLETE ===");
         Ok(())
     }
 }
 
+// Linear vesting between start and end; cliffs are checked by the caller
+fn linear_vested_amount(total_amount: u64, start: i64, end: i64, now: i64) -> Result<u64> {
+    let elapsed = now - start;
+    let duration = end - start;
+    if elapsed >= duration {
+        return Ok(total_amount);
+    }
+    Ok((total_amount as u128)
+        .checked_mul(elapsed as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(duration as u128)
+        .ok_or(ErrorCode::MathOverflow)? as u64)
+}
+
+// A distribution reissuing the original airdrop list skips every wallet that already
+// claimed it through the legacy ["airdrop_vesting", user] account
+fn check_legacy_airdrop_claim(reissues_legacy_airdrop: bool, has_legacy_vesting: bool) -> Result<()> {
+    require!(!(reissues_legacy_airdrop && has_legacy_vesting), ErrorCode::AlreadyClaimed);
+    Ok(())
+}
+
 // Helper function to get bonus range for a tier
 fn get_tier_bonus_range(tier: u8) -> (u16, u16) {
     match tier {
@@ -1243,7 +1548,8 @@ pub struct UpdateConfig<'info> {
 }
 
 #[derive(Accounts)]
-pub struct InitializeWhitelist<'info> {
+#[instruction(id: u64)]
+pub struct ProposeTimelock<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
     #[account(
@@ -1254,14 +1560,60 @@ pub struct InitializeWhitelist<'info> {
     #[account(
         init,
         payer = admin,
-        space = 8 + Whitelist::LEN,
-        seeds = [b"whitelist"],
-        bump,
+        space = 8 + TimelockProposal::LEN,
+        seeds = [b"timelock_proposal", id.to_le_bytes().as_ref()],
+        bump
     )]
-    pub whitelist: Account<'info, Whitelist>,
+    pub proposal: Account<'info, TimelockProposal>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct ExecuteTimelock<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"collection_config"],
+        bump
+    )]
+    pub collection_config: Box<Account<'info, CollectionConfig>>,
+    #[account(
+        mut,
+        seeds = [b"timelock_proposal", id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, TimelockProposal>,
+    // Only needed for UpdateDistributionRoot and ActivateDistribution
+    #[account(mut)]
+    pub distribution: Option<Account<'info, Distribution>>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct CancelTimelock<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"timelock_proposal", id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, TimelockProposal>,
+}
+
 #[derive(Accounts)]
 pub struct InitializeUserTax<'info> {
     #[account(mut)]
@@ -1313,6 +1665,54 @@ pub struct InitializeCollection<'info> {
 }
 
 #[derive(Accounts)]
+pub struct InitializeCollectionGroup<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        seeds = [b"collection_config"],
+        bump
+    )]
+    pub collection_config: Box<Account<'info, CollectionConfig>>,
+    /// Keypair of the configured collection mint; the mint and its group are created by the instruction
+    #[account(mut, address = collection_config.collection_mint)]
+    pub collection_mint: Signer<'info>,
+    #[account(
+        seeds = [b"escrow"],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    pub token_program_2022: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct CreateDistribution<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Distribution::LEN,
+        seeds = [b"distribution", id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub distribution: Account<'info, Distribution>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(distribution_id: u64)]
 pub struct SwapOgTier0ForPnftV6<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
@@ -1323,12 +1723,21 @@ pub struct SwapOgTier0ForPnftV6<'info> {
         bump = vrf_state.bump
     )]
     pub vrf_state: Account<'info, VrfState>,
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"collection_config"],
+        bump
+    )]
     pub collection_config: Box<Account<'info, CollectionConfig>>,
-    /// CHECK: NFT mint to be created
-    pub nft_mint: AccountInfo<'info>,
+    /// CHECK: Collection group mint; the token program verifies the group on member init
+    #[account(mut, address = collection_config.collection_mint)]
+    pub collection_mint: AccountInfo<'info>,
+    /// Fresh keypair; the mint and its metadata are created by the instruction
+    #[account(mut)]
+    pub nft_mint: Signer<'info>,
+    /// CHECK: User's NFT ATA, created by the instruction through the associated token program
     #[account(mut)]
-    pub nft_token_account: Box<InterfaceAccount<'info, TokenAccount2022>>,
+    pub nft_token_account: AccountInfo<'info>,
     #[account(
         init,
         payer = user,
@@ -1350,6 +1759,12 @@ pub struct SwapOgTier0ForPnftV6<'info> {
         bump
     )]
     pub escrow: Account<'info, Escrow>,
+    #[account(
+        mut,
+        seeds = [b"distribution", distribution_id.to_le_bytes().as_ref()],
+        bump = distribution.bump
+    )]
+    pub distribution: Box<Account<'info, Distribution>>,
     #[account(
         init_if_needed,
         payer = user,
@@ -1360,6 +1775,7 @@ pub struct SwapOgTier0ForPnftV6<'info> {
     pub og_tier0_claim: Box<Account<'info, OgTier0Claim>>,
     pub system_program: Program<'info, System>,
     pub token_program_2022: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     /// CHECK: Sysvar for recent blockhashes
     #[account(address = solana_program::sysvar::recent_blockhashes::ID)]
     pub recent_blockhashes: AccountInfo<'info>,
@@ -1394,12 +1810,21 @@ pub struct SwapDefaiForPnftV6<'info> {
     /// CHECK: DEFAI mint
     pub defai_mint: AccountInfo<'info>,
     pub config: Account<'info, Config>,
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"collection_config"],
+        bump
+    )]
     pub collection_config: Box<Account<'info, CollectionConfig>>,
-    /// CHECK: NFT mint to be created
-    pub nft_mint: AccountInfo<'info>,
+    /// CHECK: Collection group mint; the token program verifies the group on member init
+    #[account(mut, address = collection_config.collection_mint)]
+    pub collection_mint: AccountInfo<'info>,
+    /// Fresh keypair; the mint and its metadata are created by the instruction
+    #[account(mut)]
+    pub nft_mint: Signer<'info>,
+    /// CHECK: User's NFT ATA, created by the instruction through the associated token program
     #[account(mut)]
-    pub nft_token_account: Box<InterfaceAccount<'info, TokenAccount2022>>,
+    pub nft_token_account: AccountInfo<'info>,
     #[account(
         init,
         payer = user,
@@ -1429,6 +1854,7 @@ pub struct SwapDefaiForPnftV6<'info> {
     pub user_tax_state: Box<Account<'info, UserTaxState>>,
     pub system_program: Program<'info, System>,
     pub token_program_2022: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     /// CHECK: Sysvar for recent blockhashes
     #[account(address = solana_program::sysvar::recent_blockhashes::ID)]
     pub recent_blockhashes: AccountInfo<'info>,
@@ -1463,12 +1889,21 @@ pub struct SwapOldDefaiForPnftV6<'info> {
     )]
     pub vrf_state: Account<'info, VrfState>,
     pub config: Account<'info, Config>,
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"collection_config"],
+        bump
+    )]
     pub collection_config: Box<Account<'info, CollectionConfig>>,
-    /// CHECK: NFT mint to be created
-    pub nft_mint: AccountInfo<'info>,
+    /// CHECK: Collection group mint; the token program verifies the group on member init
+    #[account(mut, address = collection_config.collection_mint)]
+    pub collection_mint: AccountInfo<'info>,
+    /// Fresh keypair; the mint and its metadata are created by the instruction
     #[account(mut)]
-    pub nft_token_account: Box<InterfaceAccount<'info, TokenAccount2022>>,
+    pub nft_mint: Signer<'info>,
+    /// CHECK: User's NFT ATA, created by the instruction through the associated token program
+    #[account(mut)]
+    pub nft_token_account: AccountInfo<'info>,
     #[account(
         init,
         payer = user,
@@ -1499,6 +1934,7 @@ pub struct SwapOldDefaiForPnftV6<'info> {
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub token_program_2022: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     /// CHECK: Sysvar for recent blockhashes
     #[account(address = solana_program::sysvar::recent_blockhashes::ID)]
     pub recent_blockhashes: AccountInfo<'info>,
@@ -1682,7 +2118,14 @@ pub struct RerollBonusV6<'info> {
 
 #[derive(Accounts)]
 pub struct UpdateNftMetadataV6<'info> {
-    /// CHECK: NFT mint
+    /// Anyone can refresh metadata; pays any extra rent as the metadata grows
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: NFT mint holding the token metadata extension; tied to the program by the PDAs below
+    #[account(
+        mut,
+        owner = token_program_2022.key()
+    )]
     pub nft_mint: AccountInfo<'info>,
     #[account(
         seeds = [b"bonus_v6", nft_mint.key().as_ref()],
@@ -1694,20 +2137,80 @@ pub struct UpdateNftMetadataV6<'info> {
         bump
     )]
     pub vesting_state: Account<'info, VestingStateV6>,
+    #[account(
+        seeds = [b"escrow"],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    pub system_program: Program<'info, System>,
+    pub token_program_2022: Program<'info, Token2022>,
 }
 
 #[derive(Accounts)]
+#[instruction(distribution_id: u64)]
 pub struct ClaimAirdrop<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
     #[account(
         init,
         payer = user,
-        space = 8 + AirdropVesting::LEN,
+        space = 8 + DistributionVesting::LEN,
+        seeds = [b"airdrop_vesting", distribution_id.to_le_bytes().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub airdrop_vesting: Account<'info, DistributionVesting>,
+    /// CHECK: Only checked for existence; claims against the original airdrop root live here
+    #[account(
         seeds = [b"airdrop_vesting", user.key().as_ref()],
         bump
     )]
-    pub airdrop_vesting: Account<'info, AirdropVesting>,
+    pub legacy_airdrop_vesting: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"distribution", distribution_id.to_le_bytes().as_ref()],
+        bump = distribution.bump
+    )]
+    pub distribution: Account<'info, Distribution>,
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(distribution_id: u64)]
+pub struct ClaimVestedAirdrop<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"airdrop_vesting", distribution_id.to_le_bytes().as_ref(), user.key().as_ref()],
+        bump,
+        constraint = airdrop_vesting.beneficiary == user.key()
+    )]
+    pub airdrop_vesting: Account<'info, DistributionVesting>,
+    #[account(
+        mut,
+        token::mint = defai_mint,
+        token::authority = user
+    )]
+    pub user_defai_ata: InterfaceAccount<'info, TokenAccount2022>,
+    #[account(
+        mut,
+        // Ensure escrow token account is owned by escrow PDA and is the DEFAI mint
+        token::authority = escrow,
+        token::mint = defai_mint
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount2022>,
+    /// CHECK: DEFAI mint
+    pub defai_mint: AccountInfo<'info>,
+    #[account(
+        seeds = [b"escrow"],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
     #[account(
         seeds = [b"collection_config"],
         bump
@@ -1718,11 +2221,11 @@ pub struct ClaimAirdrop<'info> {
         bump
     )]
     pub config: Account<'info, Config>,
-    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token2022>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimVestedAirdrop<'info> {
+pub struct ClaimVestedAirdropLegacy<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
     #[account(
@@ -1806,6 +2309,7 @@ impl TaxState {
 
 #[account]
 pub struct TimelockProposal {
+    pub id: u64,
     pub proposer: Pubkey,
     pub proposal_type: ProposalType,
     pub execute_after: i64,
@@ -1814,13 +2318,23 @@ pub struct TimelockProposal {
 }
 
 impl TimelockProposal {
-    pub const LEN: usize = 32 + 64 + 8 + 1 + 1; // Adjust based on ProposalType size
+    pub const LEN: usize = 8 + 32 + ProposalType::LEN + 8 + 1 + 1;
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub enum ProposalType {
     UpdatePrices { prices: [u64; 5] },
     UpdateTreasury { new_treasury: Pubkey },
+    UpdateTierSupplies { supplies: [u16; 5] },
+    UpdateDistributionRoot { distribution_id: u64, merkle_root: [u8; 32], total_amount: u64 },
+    ActivateDistribution { distribution_id: u64 },
+    SetVrfEnabled { enabled: bool },
+    SetPaused { paused: bool },
+}
+
+impl ProposalType {
+    // Variant tag plus the largest variant (UpdateDistributionRoot)
+    pub const LEN: usize = 1 + 8 + 32 + 8;
 }
 
 #[account]
@@ -1836,9 +2350,9 @@ pub struct CollectionConfig {
     pub tier_supplies: [u16; 5],
     pub tier_minted: [u16; 5],
     pub tier_uri_prefixes: [String; 5],
-    // MAY20DEFAIHolders.csv: OG Tier 0 holders who can mint NFT + get 1:1 vesting from Quantity column
+    // MAY20DEFAIHolders.csv: OG Tier 0 root claimed before distributions existed (unused by new collections)
     pub og_tier_0_merkle_root: [u8; 32],
-    // 10_1AIR-Sheet1.csv: Airdrop recipients who get vesting only (NO NFT) from AIRDROP column
+    // 10_1AIR-Sheet1.csv: airdrop root claimed before distributions existed (unused by new collections)
     pub airdrop_merkle_root: [u8; 32],
     pub og_tier_0_supply: u16,      // Reserved supply for OG holders
     pub og_tier_0_minted: u16,      // Counter for OG claims
@@ -1889,14 +2403,46 @@ impl UserTaxState {
     pub const LEN: usize = 32 + 2 + 8 + 4;
 }
 
+// A Merkle distribution round; leaves are keccak(user || amount_le)
 #[account]
-pub struct Whitelist {
-    pub root: [u8; 32],
+pub struct Distribution {
+    pub id: u64,
+    pub kind: DistributionKind,
+    pub merkle_root: [u8; 32],
+    pub total_amount: u64,      // Cap on the sum of claimed amounts
+    pub claimed_amount: u64,
     pub claimed_count: u32,
+    pub cliff_duration: i64,
+    pub vesting_duration: i64,
+    pub start_timestamp: i64,   // Claim window start
+    pub end_timestamp: i64,     // Claim window end
+    pub reissues_legacy_airdrop: bool,  // Reissues the original airdrop list; legacy claimers are rejected
+    pub active: bool,           // Set by an executed ActivateDistribution proposal
+    pub bump: u8,
 }
 
-impl Whitelist {
-    pub const LEN: usize = 32 + 4;
+impl Distribution {
+    pub const LEN: usize = 8 + 1 + 32 + 8 + 8 + 4 + 8 + 8 + 8 + 8 + 1 + 1 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DistributionKind {
+    // NFT mint plus 1:1 vesting (MAY20DEFAIHolders.csv)
+    OgTier0,
+    // Vesting only, no NFT (10_1AIR-Sheet1.csv)
+    Airdrop,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct DistributionParams {
+    pub kind: DistributionKind,
+    pub merkle_root: [u8; 32],
+    pub total_amount: u64,
+    pub cliff_duration: i64,
+    pub vesting_duration: i64,
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    pub reissues_legacy_airdrop: bool,
 }
 
 #[account]
@@ -1909,6 +2455,7 @@ impl OgTier0Claim {
     pub const LEN: usize = 32 + 1;
 }
 
+// Vesting from the original airdrop root, at ["airdrop_vesting", user]
 #[account]
 pub struct AirdropVesting {
     pub beneficiary: Pubkey,
@@ -1923,6 +2470,23 @@ impl AirdropVesting {
     pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 8;
 }
 
+// Vesting from an airdrop distribution, at ["airdrop_vesting", id_le, user]
+#[account]
+pub struct DistributionVesting {
+    pub beneficiary: Pubkey,
+    pub distribution_id: u64,
+    pub total_amount: u64,
+    pub released_amount: u64,
+    pub start_timestamp: i64,
+    pub cliff_timestamp: i64,
+    pub end_timestamp: i64,
+    pub last_claimed_timestamp: i64,
+}
+
+impl DistributionVesting {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8;
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Insufficient OLD tokens provided.")]
@@ -1981,6 +2545,18 @@ pub enum ErrorCode {
     VrfAlreadyEnabled,
     #[msg("VRF result not ready")]
     VrfNotReady,
+    #[msg("Invalid vesting schedule")]
+    InvalidVestingSchedule,
+    #[msg("Distribution is not open for claims")]
+    DistributionNotActive,
+    #[msg("Distribution total amount exhausted")]
+    DistributionExhausted,
+    #[msg("Distribution kind does not match this claim")]
+    WrongDistributionKind,
+    #[msg("Proposal already executed or cancelled")]
+    ProposalNotPending,
+    #[msg("Distribution account missing or does not match the proposal")]
+    MissingDistribution,
 }
 
 // ===== Events =====
@@ -2042,6 +2618,7 @@ pub struct TaxReset {
 #[event]
 pub struct AirdropClaimed {
     pub user: Pubkey,
+    pub distribution_id: u64,
     pub amount: u64,
     pub vesting_start: i64,
     pub vesting_end: i64,
@@ -2050,7 +2627,96 @@ pub struct AirdropClaimed {
 #[event]
 pub struct AirdropVestingClaimed {
     pub user: Pubkey,
+    pub distribution_id: u64,
     pub amount_claimed: u64,
     pub total_vested: u64,
     pub timestamp: i64,
 }
+
+#[event]
+pub struct NftMetadataUpdated {
+    pub nft_mint: Pubkey,
+    pub tier: u8,
+    pub bonus_bps: u16,
+    pub redeemed: bool,
+    pub vesting_total: u64,
+    pub vesting_released: u64,
+    pub vesting_claimable: u64,
+    pub days_remaining: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OgTier0Claimed {
+    pub user: Pubkey,
+    pub distribution_id: u64,
+    pub nft_mint: Pubkey,
+    pub vesting_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DistributionCreated {
+    pub id: u64,
+    pub kind: DistributionKind,
+    pub merkle_root: [u8; 32],
+    pub total_amount: u64,
+    pub cliff_duration: i64,
+    pub vesting_duration: i64,
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    pub reissues_legacy_airdrop: bool,
+}
+
+#[event]
+pub struct CollectionGroupInitialized {
+    pub collection_mint: Pubkey,
+    pub update_authority: Pubkey,
+    pub max_size: u32,
+}
+
+#[event]
+pub struct TimelockProposed {
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub proposal_type: ProposalType,
+    pub execute_after: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TimelockExecuted {
+    pub id: u64,
+    pub executor: Pubkey,
+    pub proposal_type: ProposalType,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TimelockCancelled {
+    pub id: u64,
+    pub canceller: Pubkey,
+    pub timestamp: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reissued_airdrop_rejects_legacy_claimers() {
+        let err = check_legacy_airdrop_claim(true, true).unwrap_err();
+        assert_eq!(err, ErrorCode::AlreadyClaimed.into());
+    }
+
+    #[test]
+    fn reissued_airdrop_accepts_new_claimers() {
+        assert!(check_legacy_airdrop_claim(true, false).is_ok());
+    }
+
+    #[test]
+    fn new_airdrop_ignores_legacy_claims() {
+        assert!(check_legacy_airdrop_claim(false, true).is_ok());
+        assert!(check_legacy_airdrop_claim(false, false).is_ok());
+    }
+}
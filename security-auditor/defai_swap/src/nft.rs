@@ -0,0 +1,409 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::system_program::{self, CreateAccount, Transfer};
+use anchor_spl::associated_token::{self, Create};
+use anchor_spl::token_2022::{self as token22, InitializeMint2, MintTo, SetAuthority};
+use anchor_spl::token_2022::spl_token_2022::instruction::AuthorityType;
+use spl_token_2022::extension::{
+    group_member_pointer, group_pointer, metadata_pointer, BaseStateWithExtensions, ExtensionType,
+    StateWithExtensions,
+};
+use spl_token_2022::state::Mint;
+use spl_token_group_interface::instruction as group_instruction;
+use spl_token_metadata_interface::instruction as metadata_instruction;
+use spl_token_metadata_interface::state::{Field, TokenMetadata};
+use crate::{CollectionConfig, ErrorCode};
+
+// Additional metadata keys written onto every bonus NFT
+pub const TIER_KEY: &str = "tier";
+pub const BONUS_BPS_KEY: &str = "bonus_bps";
+pub const REDEEMED_KEY: &str = "redeemed";
+pub const FEE_DEDUCTED_KEY: &str = "fee_deducted";
+pub const VESTING_TOTAL_KEY: &str = "vesting_total";
+pub const VESTING_RELEASED_KEY: &str = "vesting_released";
+pub const VESTING_CLAIMABLE_KEY: &str = "vesting_claimable";
+pub const DAYS_REMAINING_KEY: &str = "days_remaining";
+
+/// Accounts needed to create the collection mint as a Token-2022 group.
+/// `authority` is the escrow PDA, which becomes both the mint authority and
+/// the group update authority.
+pub struct CollectionGroupAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub collection_mint: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+/// Creates the collection mint with the group pointer and token group
+/// extensions, both living on the mint itself. No tokens are ever minted
+/// from it; it only anchors group membership of the bonus NFTs.
+pub fn create_collection_group(
+    accounts: CollectionGroupAccounts<'_, '_>,
+    max_size: u32,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let token_program_id = accounts.token_program.key();
+    let mint_key = accounts.collection_mint.key();
+    let authority_key = accounts.authority.key();
+
+    // The group extension is written after the mint is initialized and
+    // reallocs the account, so fund rent for the final size up front
+    let mint_space = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::GroupPointer])?;
+    let group_space = ExtensionType::try_calculate_account_len::<Mint>(&[
+        ExtensionType::GroupPointer,
+        ExtensionType::TokenGroup,
+    ])?;
+    system_program::create_account(
+        CpiContext::new(
+            accounts.system_program.clone(),
+            CreateAccount {
+                from: accounts.payer.clone(),
+                to: accounts.collection_mint.clone(),
+            },
+        ),
+        Rent::get()?.minimum_balance(group_space),
+        mint_space as u64,
+        &token_program_id,
+    )?;
+
+    invoke(
+        &group_pointer::instruction::initialize(
+            &token_program_id,
+            &mint_key,
+            Some(authority_key),
+            Some(mint_key),
+        )?,
+        std::slice::from_ref(accounts.collection_mint),
+    )?;
+
+    token22::initialize_mint2(
+        CpiContext::new(
+            accounts.token_program.clone(),
+            InitializeMint2 {
+                mint: accounts.collection_mint.clone(),
+            },
+        ),
+        0,
+        &authority_key,
+        None,
+    )?;
+
+    invoke_signed(
+        &group_instruction::initialize_group(
+            &token_program_id,
+            &mint_key,
+            &mint_key,
+            &authority_key,
+            Some(authority_key),
+            max_size,
+        ),
+        &[
+            accounts.collection_mint.clone(),
+            accounts.collection_mint.clone(),
+            accounts.authority.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    Ok(())
+}
+
+/// Accounts needed to create a bonus NFT mint with Token-2022 metadata.
+/// `authority` is the escrow PDA: it is the mint authority while minting
+/// and stays on as the metadata update authority afterwards. It also signs
+/// as the update authority of the `collection_mint` group.
+pub struct BonusNftAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub owner: &'a AccountInfo<'info>,
+    pub nft_mint: &'a AccountInfo<'info>,
+    pub collection_mint: &'a AccountInfo<'info>,
+    pub nft_token_account: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub associated_token_program: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+/// Builds the metadata for the `serial`-th NFT of a tier from the collection config
+pub fn tier_metadata(
+    config: &CollectionConfig,
+    tier: u8,
+    serial: u16,
+    nft_mint: Pubkey,
+    update_authority: Pubkey,
+) -> Result<TokenMetadata> {
+    let index = tier as usize;
+    let mut metadata = TokenMetadata {
+        update_authority: Some(update_authority).try_into()?,
+        mint: nft_mint,
+        name: format!("{} #{}", config.tier_names[index], serial),
+        symbol: config.tier_symbols[index].clone(),
+        uri: format!("{}{}.json", config.tier_uri_prefixes[index], serial),
+        additional_metadata: vec![],
+    };
+    metadata.set_key_value(TIER_KEY.to_string(), tier.to_string());
+    Ok(metadata)
+}
+
+/// Builds the metadata for the `serial`-th OG tier 0 claim. OG claims have their own
+/// serial range, so their name and URI are marked to keep them apart from public tier 0
+pub fn og_metadata(
+    config: &CollectionConfig,
+    serial: u16,
+    nft_mint: Pubkey,
+    update_authority: Pubkey,
+) -> Result<TokenMetadata> {
+    let mut metadata = tier_metadata(config, 0, serial, nft_mint, update_authority)?;
+    metadata.name = format!("{} OG #{}", config.tier_names[0], serial);
+    metadata.uri = format!("{}og-{}.json", config.tier_uri_prefixes[0], serial);
+    Ok(metadata)
+}
+
+/// Creates the NFT mint with the metadata pointer, token metadata and group
+/// member pointer extensions, adds it to the collection group, mints exactly
+/// one token to the owner's ATA and then drops the mint authority
+pub fn mint_bonus_nft(
+    accounts: BonusNftAccounts<'_, '_>,
+    metadata: &TokenMetadata,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let token_program_id = accounts.token_program.key();
+    let mint_key = accounts.nft_mint.key();
+    let authority_key = accounts.authority.key();
+    require_keys_eq!(metadata.mint, mint_key, ErrorCode::InvalidNft);
+
+    // The metadata and group member extensions are written after the mint is
+    // initialized and realloc it, so fund rent for the final size up front
+    let mint_space = ExtensionType::try_calculate_account_len::<Mint>(&[
+        ExtensionType::MetadataPointer,
+        ExtensionType::GroupMemberPointer,
+    ])?;
+    let member_space = ExtensionType::try_calculate_account_len::<Mint>(&[
+        ExtensionType::MetadataPointer,
+        ExtensionType::GroupMemberPointer,
+        ExtensionType::TokenGroupMember,
+    ])?;
+    let lamports = Rent::get()?.minimum_balance(member_space + metadata.tlv_size_of()?);
+    system_program::create_account(
+        CpiContext::new(
+            accounts.system_program.clone(),
+            CreateAccount {
+                from: accounts.payer.clone(),
+                to: accounts.nft_mint.clone(),
+            },
+        ),
+        lamports,
+        mint_space as u64,
+        &token_program_id,
+    )?;
+
+    // Metadata lives on the mint itself
+    invoke(
+        &metadata_pointer::instruction::initialize(
+            &token_program_id,
+            &mint_key,
+            Some(authority_key),
+            Some(mint_key),
+        )?,
+        std::slice::from_ref(accounts.nft_mint),
+    )?;
+
+    // So is the group membership
+    invoke(
+        &group_member_pointer::instruction::initialize(
+            &token_program_id,
+            &mint_key,
+            Some(authority_key),
+            Some(mint_key),
+        )?,
+        std::slice::from_ref(accounts.nft_mint),
+    )?;
+
+    token22::initialize_mint2(
+        CpiContext::new(
+            accounts.token_program.clone(),
+            InitializeMint2 {
+                mint: accounts.nft_mint.clone(),
+            },
+        ),
+        0,
+        &authority_key,
+        None,
+    )?;
+
+    invoke_signed(
+        &metadata_instruction::initialize(
+            &token_program_id,
+            &mint_key,
+            &authority_key,
+            &mint_key,
+            &authority_key,
+            metadata.name.clone(),
+            metadata.symbol.clone(),
+            metadata.uri.clone(),
+        ),
+        &[
+            accounts.nft_mint.clone(),
+            accounts.authority.clone(),
+            accounts.nft_mint.clone(),
+            accounts.authority.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    for (key, value) in metadata.additional_metadata.iter() {
+        invoke_signed(
+            &metadata_instruction::update_field(
+                &token_program_id,
+                &mint_key,
+                &authority_key,
+                Field::Key(key.clone()),
+                value.clone(),
+            ),
+            &[accounts.nft_mint.clone(), accounts.authority.clone()],
+            signer_seeds,
+        )?;
+    }
+
+    // Bumps the group size; fails if the collection group was never created
+    invoke_signed(
+        &group_instruction::initialize_member(
+            &token_program_id,
+            &mint_key,
+            &mint_key,
+            &authority_key,
+            &accounts.collection_mint.key(),
+            &authority_key,
+        ),
+        &[
+            accounts.nft_mint.clone(),
+            accounts.nft_mint.clone(),
+            accounts.authority.clone(),
+            accounts.collection_mint.clone(),
+            accounts.authority.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    associated_token::create(CpiContext::new(
+        accounts.associated_token_program.clone(),
+        Create {
+            payer: accounts.payer.clone(),
+            associated_token: accounts.nft_token_account.clone(),
+            authority: accounts.owner.clone(),
+            mint: accounts.nft_mint.clone(),
+            system_program: accounts.system_program.clone(),
+            token_program: accounts.token_program.clone(),
+        },
+    ))?;
+
+    token22::mint_to(
+        CpiContext::new_with_signer(
+            accounts.token_program.clone(),
+            MintTo {
+                mint: accounts.nft_mint.clone(),
+                to: accounts.nft_token_account.clone(),
+                authority: accounts.authority.clone(),
+            },
+            signer_seeds,
+        ),
+        1,
+    )?;
+
+    // Supply is fixed at one
+    token22::set_authority(
+        CpiContext::new_with_signer(
+            accounts.token_program.clone(),
+            SetAuthority {
+                current_authority: accounts.authority.clone(),
+                account_or_mint: accounts.nft_mint.clone(),
+            },
+            signer_seeds,
+        ),
+        AuthorityType::MintTokens,
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// Accounts needed to rewrite additional metadata on an existing bonus NFT.
+/// `payer` covers any extra rent when the metadata grows.
+pub struct MetadataUpdateAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub nft_mint: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+/// Writes the given key/value pairs into the mint's token metadata, skipping
+/// values that are already current
+pub fn update_metadata_fields(
+    accounts: MetadataUpdateAccounts<'_, '_>,
+    fields: &[(&str, String)],
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let (old_size, mut metadata) = {
+        let data = accounts.nft_mint.try_borrow_data()?;
+        let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+        let metadata = mint.get_variable_len_extension::<TokenMetadata>()?;
+        (metadata.tlv_size_of()?, metadata)
+    };
+
+    let changed: Vec<(&str, String)> = fields
+        .iter()
+        .filter(|(key, value)| {
+            !metadata
+                .additional_metadata
+                .iter()
+                .any(|(k, v)| k == key && v == value)
+        })
+        .cloned()
+        .collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    // Top up rent for the grown metadata before the token program reallocs the mint
+    for (key, value) in changed.iter() {
+        metadata.set_key_value(key.to_string(), value.clone());
+    }
+    let new_len = accounts
+        .nft_mint
+        .data_len()
+        .saturating_sub(old_size)
+        .saturating_add(metadata.tlv_size_of()?);
+    let required = Rent::get()?.minimum_balance(new_len);
+    let shortfall = required.saturating_sub(accounts.nft_mint.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.clone(),
+                Transfer {
+                    from: accounts.payer.clone(),
+                    to: accounts.nft_mint.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+
+    let token_program_id = accounts.token_program.key();
+    for (key, value) in changed {
+        invoke_signed(
+            &metadata_instruction::update_field(
+                &token_program_id,
+                &accounts.nft_mint.key(),
+                &accounts.authority.key(),
+                Field::Key(key.to_string()),
+                value,
+            ),
+            &[accounts.nft_mint.clone(), accounts.authority.clone()],
+            signer_seeds,
+        )?;
+    }
+
+    Ok(())
+}